@@ -1,10 +1,11 @@
 use std::{
     collections::{HashMap, HashSet},
     path::PathBuf,
+    str::FromStr,
     sync::{LazyLock, Mutex, RwLock},
 };
 
-use log::info;
+use log::{info, warn};
 use semver::Version;
 use skyline::nn;
 use skyline_config::*;
@@ -82,10 +83,27 @@ pub fn skip_title_scene() -> bool {
 
 pub static REGION: RwLock<Region> = RwLock::new(Region::UsEnglish);
 
+// Region the files of mods are picked for instead of the one of the game, such as "jp_ja"
+static REGION_OVERRIDE: LazyLock<Option<Region>> = LazyLock::new(|| {
+    let region_override: String = GLOBAL_CONFIG.lock().unwrap().get_field("region_override").ok()?;
+
+    match Region::from_str(&region_override) {
+        Ok(Region::None) | Err(_) => {
+            warn!("Region override '{}' is not a valid region and will be ignored.", region_override);
+            None
+        },
+        Ok(region) => Some(region),
+    }
+});
+
 pub fn region() -> Region {
     *REGION.read().unwrap()
 }
 
+pub fn asset_region() -> Region {
+    REGION_OVERRIDE.unwrap_or_else(region)
+}
+
 pub fn logger_level() -> String {
     let level: String = GLOBAL_CONFIG
         .lock()
@@ -107,6 +125,66 @@ pub fn use_folder_name() -> bool {
     GLOBAL_CONFIG.lock().unwrap().get_flag("use_folder_name")
 }
 
+pub fn disabled_mods() -> HashSet<String> {
+    GLOBAL_CONFIG.lock().unwrap().get_field_json("disabled_mods").unwrap_or_default()
+}
+
+pub fn discovery_exclusions() -> Vec<String> {
+    GLOBAL_CONFIG.lock().unwrap().get_field_json("discovery_exclusions").unwrap_or_default()
+}
+
+pub fn ignored_prefixes() -> Vec<String> {
+    GLOBAL_CONFIG
+        .lock()
+        .unwrap()
+        .get_field_json("ignored_prefixes")
+        .unwrap_or_else(|_| vec![String::from(".")])
+}
+
+pub fn ignored_suffixes() -> Vec<String> {
+    GLOBAL_CONFIG.lock().unwrap().get_field_json("ignored_suffixes").unwrap_or_default()
+}
+
+pub fn randomize_per_session() -> bool {
+    GLOBAL_CONFIG.lock().unwrap().get_flag("randomize_per_session")
+}
+
+pub fn strict_manifests() -> bool {
+    GLOBAL_CONFIG.lock().unwrap().get_flag("strict_manifests")
+}
+
+pub fn json_patch_append_arrays() -> bool {
+    GLOBAL_CONFIG.lock().unwrap().get_flag("json_patch_append_arrays")
+}
+
+pub fn shrink_files() -> bool {
+    GLOBAL_CONFIG.lock().unwrap().get_flag("shrink_files")
+}
+
+pub fn dry_run() -> bool {
+    GLOBAL_CONFIG.lock().unwrap().get_flag("dry_run")
+}
+
+pub fn allow_empty_files() -> bool {
+    GLOBAL_CONFIG.lock().unwrap().get_flag("allow_empty_files")
+}
+
+pub fn file_cache_size() -> usize {
+    GLOBAL_CONFIG.lock().unwrap().get_field("file_cache_size").unwrap_or(0)
+}
+
+pub fn direct_read_threshold() -> usize {
+    GLOBAL_CONFIG.lock().unwrap().get_field("direct_read_threshold").unwrap_or(0)
+}
+
+pub fn virtual_root() -> String {
+    GLOBAL_CONFIG
+        .lock()
+        .unwrap()
+        .get_field("virtual_root")
+        .unwrap_or_else(|_| String::from("api:/"))
+}
+
 pub fn set_mod_cache(cache: &HashSet<Hash40>) -> Result<(), ConfigError> {
     GLOBAL_CONFIG.lock().unwrap().set_field_json("mod_cache", &cache)
 }
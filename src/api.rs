@@ -1,6 +1,7 @@
 pub mod callback;
 pub mod event;
 pub mod file;
+pub mod observer;
 pub mod utils;
 pub mod lua;
 
@@ -20,7 +21,7 @@ pub struct ApiVersion {
 /// Do your changes only add new APIs in a backwards compatible way: Minor bump
 ///
 /// Are your changes only internal? No version bump
-static API_VERSION: ApiVersion = ApiVersion { major: 1, minor: 9 };
+static API_VERSION: ApiVersion = ApiVersion { major: 1, minor: 10 };
 
 #[no_mangle]
 pub extern "C" fn arcrop_api_version() -> &'static ApiVersion {
@@ -6,16 +6,101 @@ use smash_arc::Hash40;
 
 use crate::{fs::*, hashes};
 
+/// Called with the hash of the file, the offset to write at and a buffer of the provided length to write into.
+/// Returns how many bytes were written, and is called again until it returns 0 or the file is complete.
+pub type ChunkCallbackFn = extern "C" fn(u64, usize, *mut u8, usize) -> usize;
+
+#[derive(Clone, Copy)]
 pub enum PendingApiCall {
     GenericCallback { hash: Hash40, max_size: usize, callback: CallbackFn },
+    ChunkCallback { hash: Hash40, max_size: usize, callback: ChunkCallbackFn },
     StreamCallback { hash: Hash40, callback: StreamCallbackFn },
 }
 
 unsafe impl Send for PendingApiCall {}
 unsafe impl Sync for PendingApiCall {}
 
+/// API calls submitted before the filesystem is initialized, which are all handled when it gets built
 pub static PENDING_CALLBACKS: LazyLock<Mutex<Vec<PendingApiCall>>> = LazyLock::new(|| Mutex::new(Vec::new()));
 
+/// Handles the API call right away if the filesystem is initialized, otherwise queues it up until it is
+fn submit_api_call(request: PendingApiCall) {
+    // The filesystem sets its initialized flag while holding this lock, so the call can't be queued after the queue has been handled
+    let mut pending_calls = PENDING_CALLBACKS.lock().unwrap();
+
+    if GlobalFilesystem::is_init() {
+        // The flag is never unset, so there is no need to keep the queue locked while waiting on the filesystem
+        drop(pending_calls);
+        unsafe { crate::GLOBAL_FILESYSTEM.write().unwrap().handle_api_request(request) };
+    } else {
+        debug!("Pushing to pending calls!");
+        pending_calls.push(request);
+    }
+}
+
+/// Same as `submit_api_call`, for several calls at once. Either all of them are queued or all of them are handled,
+/// and the filesystem is only locked once for the whole batch.
+fn submit_api_calls<I: IntoIterator<Item = PendingApiCall>>(requests: I) {
+    let mut pending_calls = PENDING_CALLBACKS.lock().unwrap();
+
+    if GlobalFilesystem::is_init() {
+        drop(pending_calls);
+        let mut filesystem = unsafe { crate::GLOBAL_FILESYSTEM.write().unwrap() };
+
+        for request in requests {
+            filesystem.handle_api_request(request);
+        }
+    } else {
+        debug!("Pushing batch to pending calls!");
+        pending_calls.extend(requests);
+    }
+}
+
+/// A file provided through a callback, as registered in batches with `arcrop_register_callbacks`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct CallbackEntry {
+    pub hash: Hash40,
+    pub max_size: usize,
+    pub callback: CallbackFn,
+}
+
+/// Registers a callback which will be called to fill the buffer of the file when the game loads it
+pub fn register_callback<H: Into<Hash40>>(hash: H, max_size: usize, callback: CallbackFn) {
+    submit_api_call(PendingApiCall::GenericCallback {
+        hash: hash.into(),
+        max_size,
+        callback,
+    });
+}
+
+/// Registers a callback which will be called repeatedly to fill the buffer of the file chunk by chunk when the game loads it
+pub fn register_chunk_callback<H: Into<Hash40>>(hash: H, max_size: usize, callback: ChunkCallbackFn) {
+    submit_api_call(PendingApiCall::ChunkCallback {
+        hash: hash.into(),
+        max_size,
+        callback,
+    });
+}
+
+/// Registers the callbacks of several files at once, which become available to the game all at the same time
+pub fn register_callbacks(entries: &[CallbackEntry]) {
+    submit_api_calls(entries.iter().map(|entry| PendingApiCall::GenericCallback {
+        hash: entry.hash,
+        max_size: entry.max_size,
+        callback: entry.callback,
+    }));
+}
+
+/// Registers a callback which will be called to provide the path of a stream file when the game loads it
+pub fn register_stream_callback<H: Into<Hash40>>(hash: H, callback: StreamCallbackFn) {
+    submit_api_call(PendingApiCall::StreamCallback { hash: hash.into(), callback });
+}
+
+/// Provides the file for the hash through the callback, in a buffer of `max_size` bytes.
+///
+/// Once the filesystem is built, the size of the file in the data.arc is patched right away, so this must not be called
+/// while the game is loading that file. Registering before the filesystem is built, such as in the main of a plugin, is always fine.
 #[no_mangle]
 pub extern "C" fn arcrop_register_callback(hash: Hash40, max_size: usize, cb: CallbackFn) {
     debug!(
@@ -24,19 +109,32 @@ pub extern "C" fn arcrop_register_callback(hash: Hash40, max_size: usize, cb: Ca
         hash.0
     );
 
-    let request = PendingApiCall::GenericCallback {
-        hash,
-        max_size,
-        callback: cb,
-    };
+    register_callback(hash, max_size, cb);
+}
 
-    let mut pending_calls = PENDING_CALLBACKS.lock().unwrap();
+/// Same as `arcrop_register_callback`, with a callback filling the buffer chunk by chunk. The same requirement on when it can be called applies.
+#[no_mangle]
+pub extern "C" fn arcrop_register_chunk_callback(hash: Hash40, max_size: usize, cb: ChunkCallbackFn) {
+    debug!(
+        "arcrop_register_chunk_callback -> Hash received: {} ({:#x})",
+        hashes::find(hash).green(),
+        hash.0
+    );
 
-    if GlobalFilesystem::is_init() {
-        unsafe { crate::GLOBAL_FILESYSTEM.write().unwrap().handle_api_request(request) };
-    } else {
-        pending_calls.push(request);
+    register_chunk_callback(hash, max_size, cb);
+}
+
+/// Same as `arcrop_register_callback` for `count` entries at once. Once the filesystem is built, the game must not be loading any of these files
+/// when this is called.
+#[no_mangle]
+pub extern "C" fn arcrop_register_callbacks(entries: *const CallbackEntry, count: usize) {
+    debug!("arcrop_register_callbacks -> Received {} entries", count);
+
+    if entries.is_null() || count == 0 {
+        return;
     }
+
+    register_callbacks(unsafe { std::slice::from_raw_parts(entries, count) });
 }
 
 #[no_mangle]
@@ -47,16 +145,7 @@ pub extern "C" fn arcrop_register_callback_with_path(hash: Hash40, cb: StreamCal
         hash.0
     );
 
-    let request = PendingApiCall::StreamCallback { hash, callback: cb };
-
-    let mut pending_calls = PENDING_CALLBACKS.lock().unwrap();
-
-    if GlobalFilesystem::is_init() {
-        unsafe { crate::GLOBAL_FILESYSTEM.write().unwrap().handle_api_request(request) };
-    } else {
-        debug!("Pushing to pending calls!");
-        pending_calls.push(request);
-    }
+    register_stream_callback(hash, cb);
 }
 
 #[no_mangle]
@@ -1,5 +1,9 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
 
+use camino::Utf8Path;
 use owo_colors::OwoColorize;
 use smash_arc::*;
 use walkdir::WalkDir;
@@ -29,6 +33,123 @@ pub extern "C" fn arcrop_load_file(hash: Hash40, out_buffer: *mut u8, buf_length
     }
 }
 
+/// Writes the UTF-8 path of the file on the SD card that is loaded for the hash (without a null terminator) and returns its full length,
+/// which can be larger than the buffer if the path was truncated. Returns 0 if the file is not provided by a mod, or is provided by the API.
+#[no_mangle]
+pub extern "C" fn arcrop_get_physical_path(hash: Hash40, out_buffer: *mut u8, buf_length: usize) -> usize {
+    debug!(
+        "arcrop_get_physical_path -> Hash received: {} ({:#x}), Buffer len: {:#x}",
+        hashes::find(hash).green(),
+        hash.0,
+        buf_length
+    );
+
+    let path = match crate::fs::get_physical_path(hash) {
+        Some(path) => path,
+        None => return 0,
+    };
+
+    let path = path.to_string_lossy();
+    let length = path.len().min(buf_length);
+
+    if !out_buffer.is_null() {
+        unsafe { std::ptr::copy_nonoverlapping(path.as_ptr(), out_buffer, length) };
+    }
+
+    path.len()
+}
+
+/// Starts reading the files in the background, so that they load instantly once the game requests them
+#[no_mangle]
+pub extern "C" fn arcrop_prefetch_files(hashes: *const Hash40, count: usize) {
+    debug!("arcrop_prefetch_files -> Received {} hashes", count);
+
+    if hashes.is_null() || count == 0 {
+        return;
+    }
+
+    crate::fs::prefetch(unsafe { std::slice::from_raw_parts(hashes, count) });
+}
+
+/// Makes the game load the file at the provided UTF-8 path (such as `sd:/...`, without a null terminator) in place of the file
+/// for the hash, until it gets removed with `arcrop_remove_manual_override`. Returns false if the path is not an existing file.
+///
+/// The size of the file in the data.arc is patched right away, so this must not be called while the game is loading that file.
+#[no_mangle]
+pub extern "C" fn arcrop_add_manual_override(hash: Hash40, path: *const u8, length: usize) -> bool {
+    debug!(
+        "arcrop_add_manual_override -> Hash received: {} ({:#x})",
+        hashes::find(hash).green(),
+        hash.0
+    );
+
+    if path.is_null() {
+        return false;
+    }
+
+    let path = match std::str::from_utf8(unsafe { std::slice::from_raw_parts(path, length) }) {
+        Ok(path) => PathBuf::from(path),
+        Err(_) => {
+            error!("arcrop_add_manual_override -> The path provided for '{}' is not valid UTF-8.", hashes::find(hash));
+            return false;
+        },
+    };
+
+    unsafe { crate::GLOBAL_FILESYSTEM.write().unwrap().add_manual_override(hash, path) }
+}
+
+/// Removes an override added with `arcrop_add_manual_override`. Returns false if the file was not overridden through the API.
+/// The size of the file is restored right away, so the same requirement applies: the game must not be loading that file.
+#[no_mangle]
+pub extern "C" fn arcrop_remove_manual_override(hash: Hash40) -> bool {
+    debug!(
+        "arcrop_remove_manual_override -> Hash received: {} ({:#x})",
+        hashes::find(hash).green(),
+        hash.0
+    );
+
+    unsafe { crate::GLOBAL_FILESYSTEM.write().unwrap().remove_manual_override(hash) }
+}
+
+/// Writes the numbers gathered during the last discovery of the mods, for plugins that want to display or diagnose it
+#[no_mangle]
+pub extern "C" fn arcrop_get_discovery_stats(out_stats: *mut crate::fs::DiscoveryStats) -> bool {
+    debug!("arcrop_get_discovery_stats -> Function called");
+
+    if out_stats.is_null() {
+        return false;
+    }
+
+    unsafe { *out_stats = crate::fs::last_discovery_stats() };
+    true
+}
+
+/// Drops every file kept in memory, so that the next loads are read from the SD card again
+#[no_mangle]
+pub extern "C" fn arcrop_clear_file_cache() {
+    debug!("arcrop_clear_file_cache -> Function called");
+
+    unsafe { crate::GLOBAL_FILESYSTEM.read().unwrap().clear_cache() };
+}
+
+/// Discovers the mods again and rebuilds the filesystem from them, so that mods can be changed without restarting the game.
+/// The filesystem stays locked for the entire discovery, so the game can't load anything until this returns. Avoid calling it while loading.
+#[no_mangle]
+pub extern "C" fn arcrop_rescan() {
+    debug!("arcrop_rescan -> Function called");
+
+    unsafe { crate::GLOBAL_FILESYSTEM.write().unwrap().rescan() };
+}
+
+/// Checks the files of mods against the sizes they were patched for, logging every file that changed on the SD card since.
+/// Returns how many files no longer match their patch.
+#[no_mangle]
+pub extern "C" fn arcrop_verify_patches() -> usize {
+    debug!("arcrop_verify_patches -> Function called");
+
+    unsafe { crate::GLOBAL_FILESYSTEM.read().unwrap().verify_patches().len() }
+}
+
 #[no_mangle]
 pub extern "C" fn arcrop_get_decompressed_size(hash: Hash40, out_size: &mut usize) -> bool {
     debug!(
@@ -68,6 +189,11 @@ pub extern "C" fn arcrop_is_file_loaded(hash: Hash40) -> bool {
 pub extern "C" fn arcrop_is_mod_enabled(hash: Hash40) -> bool {
     debug!("arcrop_is_mod_enabled -> Received hash {} ({:#x})", hashes::find(hash).green(), hash.0);
 
+    // Mods disabled by name take priority over any preset
+    if config::disabled_mods().iter().any(|name| Hash40::from(utils::paths::mods().join(name).as_str()) == hash) {
+        return false;
+    }
+
     let storage = config::GLOBAL_CONFIG.lock().unwrap();
 
     let preset: HashSet<Hash40> = if storage.get_flag("legacy_discovery") || utils::env::is_emulator() {
@@ -83,13 +209,16 @@ pub extern "C" fn arcrop_is_mod_enabled(hash: Hash40) -> bool {
 
                     let path = entry.path();
 
-                    if path
-                        .file_name()
-                        .and_then(|name| name.to_str())
-                        .map(|name| !name.starts_with('.'))
-                        .unwrap_or(false)
-                    {
-                        Some(Hash40::from(path.to_str().unwrap()))
+                    let path = match Utf8Path::from_path(path) {
+                        Some(path) => path,
+                        None => {
+                            warn!("Mod directory '{}' does not have a valid UTF-8 name and will be skipped.", path.display());
+                            return None;
+                        },
+                    };
+
+                    if path.file_name().map(|name| !crate::fs::is_ignored_entry(name)).unwrap_or(false) {
+                        Some(Hash40::from(path.as_str()))
                     } else {
                         None
                     }
@@ -101,7 +230,13 @@ pub extern "C" fn arcrop_is_mod_enabled(hash: Hash40) -> bool {
     } else {
         let workspace_name: String = storage.get_field("workspace").unwrap_or_else(|_| "Default".to_string());
         let workspace_list: HashMap<String, String> = storage.get_field_json("workspace_list").unwrap_or_default();
-        let preset_name = &workspace_list[&workspace_name];
+        let preset_name = match workspace_list.get(&workspace_name) {
+            Some(preset_name) => preset_name,
+            None => {
+                warn!("arcrop_is_mod_enabled -> Workspace '{}' does not exist in the workspace list.", workspace_name);
+                return false;
+            },
+        };
         storage.get_field_json(preset_name).unwrap_or_default()
     };
 
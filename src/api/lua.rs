@@ -1,5 +1,5 @@
 use crate::lua::lua::{lua_state, luaL_Reg_container, luaL_Reg_from_api};
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
 
 #[no_mangle]
 pub extern "C" fn arcorp_add_lua_menu_manager(name: *mut i8, reg_vec_ptr: *mut luaL_Reg_from_api, reg_vec_size: usize, reg_vec_cap: usize) -> bool {
@@ -10,10 +10,17 @@ pub extern "C" fn arcorp_add_lua_menu_manager(name: *mut i8, reg_vec_ptr: *mut l
                 let name = s.to_string();
                 let registry = Vec::from_raw_parts(reg_vec_ptr, reg_vec_size, reg_vec_cap);
                 
-                let functions = registry.iter().map(|x| {
-                    luaL_Reg_container {
-                        name: CString::from_raw(x.name).to_str().unwrap().to_string(),
-                        func: x.func
+                // A single function with an invalid name should not bring the entire game down
+                let functions = registry.iter().filter_map(|x| {
+                    match CString::from_raw(x.name).to_str() {
+                        Ok(name) => Some(luaL_Reg_container {
+                            name: name.to_string(),
+                            func: x.func
+                        }),
+                        Err(err) => {
+                            warn!("Skipping lua function with an invalid name. Reason: {:?}", err);
+                            None
+                        }
                     }
                 }).collect::<Vec<luaL_Reg_container>>();
 
@@ -36,10 +43,17 @@ pub extern "C" fn arcorp_add_lua_ingame_manager(name: *mut i8, reg_vec_ptr: *mut
                 let name = s.to_string();
                 let registry = Vec::from_raw_parts(reg_vec_ptr, reg_vec_size, reg_vec_cap);
                 
-                let functions = registry.iter().map(|x| {
-                    luaL_Reg_container {
-                        name: CString::from_raw(x.name).to_str().unwrap().to_string(),
-                        func: x.func
+                // A single function with an invalid name should not bring the entire game down
+                let functions = registry.iter().filter_map(|x| {
+                    match CString::from_raw(x.name).to_str() {
+                        Ok(name) => Some(luaL_Reg_container {
+                            name: name.to_string(),
+                            func: x.func
+                        }),
+                        Err(err) => {
+                            warn!("Skipping lua function with an invalid name. Reason: {:?}", err);
+                            None
+                        }
                     }
                 }).collect::<Vec<luaL_Reg_container>>();
 
@@ -97,5 +111,15 @@ pub extern "C" fn arcrop_lua_state_push_nil(lua_state: &mut lua_state) {
 #[no_mangle]
 pub extern "C" fn arcrop_lua_state_push_string(lua_state: &mut lua_state, str: *mut i8) {
     debug!("arcrop_lua_state_push_string -> Function called");
-    unsafe { lua_state.push_string(CString::from_raw(str).to_str().expect("Failed to get string from str pointer!")); }
+
+    if str.is_null() {
+        warn!("arcrop_lua_state_push_string -> Received a null string, nothing was pushed.");
+        return;
+    }
+
+    // The string belongs to the caller, so it is only borrowed here
+    match unsafe { CStr::from_ptr(str as _) }.to_str() {
+        Ok(string) => lua_state.push_string(string),
+        Err(err) => warn!("arcrop_lua_state_push_string -> Received a string that is not valid UTF-8, nothing was pushed. Reason: {:?}", err),
+    }
 }
\ No newline at end of file
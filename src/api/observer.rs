@@ -0,0 +1,47 @@
+use std::{
+    path::Path,
+    sync::{LazyLock, RwLock},
+};
+
+use owo_colors::OwoColorize;
+use smash_arc::Hash40;
+
+use crate::hashes;
+
+/// Called with the hash of the file, followed by a pointer to and the length of the (non null-terminated) UTF-8 path on the SD card it was loaded from.
+/// Files that are not read from a single file on the SD card, such as the ones provided by callbacks, archives or patch files, are reported with a null pointer and a length of 0.
+pub type LoadObserverFn = extern "C" fn(Hash40, *const u8, usize);
+
+pub static LOAD_OBSERVERS: LazyLock<RwLock<Vec<LoadObserverFn>>> = LazyLock::new(|| RwLock::new(Vec::new()));
+
+#[no_mangle]
+pub extern "C" fn arcrop_register_load_observer(callback: LoadObserverFn) {
+    debug!("arcrop_register_load_observer -> Function called");
+
+    LOAD_OBSERVERS.write().unwrap().push(callback);
+}
+
+/// Lets every registered observer know that a modded file has been provided to the game.
+/// This must not be called while holding the filesystem lock, since observers are free to call back into the API.
+pub fn notify_load(hash: Hash40, path: Option<&Path>) {
+    // Copied out so that observers registering other observers don't deadlock on the lock
+    let observers = LOAD_OBSERVERS.read().unwrap().clone();
+
+    if observers.is_empty() {
+        return;
+    }
+
+    let path = path.map(Path::to_string_lossy);
+    let (ptr, len) = path.as_ref().map_or((std::ptr::null(), 0), |path| (path.as_ptr(), path.len()));
+
+    trace!(
+        "Notifying {} load observer(s) about '{}' ({:#x})",
+        observers.len(),
+        hashes::find(hash).bright_yellow(),
+        hash.0
+    );
+
+    for observer in observers.iter() {
+        observer(hash, ptr, len);
+    }
+}
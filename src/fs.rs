@@ -2,10 +2,13 @@ use std::{
     cell::UnsafeCell,
     collections::{HashMap, HashSet},
     fmt,
-    io::Write,
+    io::{Read, Write},
     ops::Deref,
     path::{Path, PathBuf},
-    sync::atomic::{AtomicBool, Ordering},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
 };
 
 use arc_config::{Config as ModConfig, ToExternal, ToSmashArc};
@@ -22,13 +25,24 @@ use crate::{
     resource, PathExtension,
 };
 
+mod cache;
 mod discover;
+mod info;
+mod manifest;
+mod overrides;
+mod prefetch;
+mod randomizer;
 mod utils;
+use cache::FileCache;
+use randomizer::RandomizerEntry;
 pub use discover::*;
+pub use info::ModInfo;
 pub mod loaders;
 pub use loaders::*;
+pub use prefetch::prefetch;
 
 static DEFAULT_CONFIG: &str = include_str!("../resources/override.json");
+// Set once the pending API calls have been handled, and never unset. From then on API calls are handled as they are submitted.
 static IS_INIT: AtomicBool = AtomicBool::new(false);
 // pub type ApiLoader = StandardLoader; // temporary until an actual ApiLoader is implemented
 
@@ -42,6 +56,15 @@ impl fmt::Debug for FilesystemUninitializedError {
     }
 }
 
+/// An override added through the API, along with everything it changed so that removing it brings the file back to how it was
+struct ManualOverride {
+    path: PathBuf,
+    previous_local: Option<PathBuf>,
+    previous_cached_size: Option<usize>,
+    previous_patched_size: Option<usize>,
+    previous_filesize: Option<usize>,
+}
+
 pub struct CachedFilesystem {
     loader: ArcropolisOrbit,
     config: ModConfig,
@@ -52,6 +75,19 @@ pub struct CachedFilesystem {
     current_nus3bank_id: u32,
     nus3banks: HashMap<Hash40, u32>,
     total_size: usize,
+    // Every API call handled so far, so that they can be applied again when rescanning
+    api_calls: Vec<api::PendingApiCall>,
+    // Original decompressed size of the files that had to be patched, so that they can be restored when rescanning
+    vanilla_sizes: HashMap<Hash40, usize>,
+    file_cache: Mutex<FileCache>,
+    // Files that did not match the manifest of their mod and must not be used
+    rejected_files: HashSet<PathBuf>,
+    // Overrides added through the API, kept so that they survive a rescan
+    manual_overrides: HashMap<Hash40, ManualOverride>,
+    // Size of every file at the time its filesize was patched, to notice files that changed on the SD card since then
+    patched_sizes: HashMap<Hash40, usize>,
+    // Size from which files on the SD card are read straight into the game's buffer, 0 if disabled
+    direct_read_threshold: usize,
 }
 
 impl CachedFilesystem {
@@ -156,6 +192,20 @@ impl CachedFilesystem {
         set
     }
 
+    /// Get a list of all JSON patch files and add them to the virtual tree
+    fn initialize_json_patches(launchpad: &LaunchPad<StandardLoader>, api_tree: &mut Tree<ApiLoader>) -> HashSet<Hash40> {
+        let mut set = HashSet::new();
+        for (root, path) in launchpad.collected_paths().iter() {
+            // The collected paths gives us everything so we only want these extensions
+            if path.has_extension("jsonx") {
+                if let Some(hash) = utils::add_json_patch(api_tree, root, path) {
+                    set.insert(hash);
+                }
+            }
+        }
+        set
+    }
+
     /// Parse a pending API call and add it to the API tree. This function returns the hash, as well as the size (if needed)
     /// so that the caller can insert those into the global structs depending on the time that this call is handled
     fn handle_panding_api_call(api_tree: &mut Tree<ApiLoader>, pending: api::PendingApiCall) -> ApiCallResult {
@@ -165,7 +215,18 @@ impl CachedFilesystem {
             PendingApiCall::GenericCallback { hash, max_size, callback } => {
                 let path = get_path_from_hash(hash);
 
-                utils::add_file_to_api_tree(api_tree, "api:/generic-cb", &path, ApiCallback::GenericCallback(callback));
+                utils::add_file_to_api_tree(api_tree, &utils::api_root("generic-cb"), &path, ApiCallback::GenericCallback(callback));
+
+                ApiCallResult {
+                    hash,
+                    path,
+                    size: Some(max_size),
+                }
+            },
+            PendingApiCall::ChunkCallback { hash, max_size, callback } => {
+                let path = get_path_from_hash(hash);
+
+                utils::add_file_to_api_tree(api_tree, &utils::api_root("chunk-cb"), &path, ApiCallback::ChunkCallback(callback));
 
                 ApiCallResult {
                     hash,
@@ -176,7 +237,7 @@ impl CachedFilesystem {
             PendingApiCall::StreamCallback { hash, callback } => {
                 let path = get_path_from_hash(hash);
 
-                utils::add_file_to_api_tree(api_tree, "api:/stream-cb", &path, ApiCallback::StreamCallback(callback));
+                utils::add_file_to_api_tree(api_tree, &utils::api_root("stream-cb"), &path, ApiCallback::StreamCallback(callback));
 
                 ApiCallResult { hash, path, size: None }
             },
@@ -189,7 +250,11 @@ impl CachedFilesystem {
         // Provide the discovered tree and get two hashmaps, one of the sizes of each file discovered (for patching)
         // and also get hash40 -> PathBuf lookup, since it's going to be a lot faster when the game is loading
         // individual files
-        let (mut hashed_sizes, mut hashed_paths) = utils::make_hash_maps(launchpad.tree());
+        let rejected_files = std::mem::take(&mut *REJECTED_FILES.lock().unwrap());
+
+        let (mut hashed_sizes, mut hashed_paths) = utils::make_hash_maps(launchpad.tree(), &rejected_files);
+
+        discover::record_discovered_size(hashed_sizes.values().sum());
 
         // Add the discovered paths to the global hashes, so that when a file is loading that *we have discovered* we can guarantee
         // that we are printing the real path in the logger.
@@ -199,6 +264,9 @@ impl CachedFilesystem {
             }
         }
 
+        // Extensions the game never uses usually mean that a file was misnamed
+        utils::report_extensions(arc, &hashed_paths);
+
         // Load the default config, which we will then join with the other configs
         let mut config = match ModConfig::from_json(DEFAULT_CONFIG) {
             Ok(cfg) => cfg,
@@ -225,6 +293,7 @@ impl CachedFilesystem {
         hashes.extend(Self::initialize_nus3audio_patches(&launchpad, &mut api_tree));
         hashes.extend(Self::initialize_motionlist_patches(&launchpad, &mut api_tree));
         hashes.extend(Self::initialize_bgm_property_patches(&launchpad, &mut api_tree));
+        hashes.extend(Self::initialize_json_patches(&launchpad, &mut api_tree));
 
         // Add the hash files and set the new size to 10x the original files
         for hash in hashes {
@@ -236,31 +305,107 @@ impl CachedFilesystem {
 
         // Add all of the NUS3BANKs that our NUS3AUDIOs depend on to the API tree
         for dep in nus3audio_deps {
-            let hash = utils::add_file_to_api_tree(&mut api_tree, "api:/patch-nus3bank", &dep, ApiCallback::None);
+            let hash = utils::add_file_to_api_tree(&mut api_tree, &utils::api_root("patch-nus3bank"), &dep, ApiCallback::None);
             if let Some(hash) = hash {
                 hashed_paths.insert(hash, dep);
                 hashed_sizes.insert(hash, 0); // We want to use vanilla size because we are only editing the content
             }
         }
 
-        // Lock the pending callbacks and then swap the memory so that we can release lock on callbacks
+        // Directories named like a file serve one of the files they contain in its place
+        for (local, candidates) in utils::collect_randomizers(launchpad.tree()) {
+            let entry = RandomizerEntry::new(candidates);
+
+            if let Some(hash) = utils::add_file_to_api_tree(&mut api_tree, &utils::api_root("randomizer"), &local, ApiCallback::None) {
+                if let Some(string) = local.to_str() {
+                    hashes::add(string);
+                }
+
+                hashed_sizes.insert(hash, entry.max_size());
+                hashed_paths.insert(hash, local);
+                api_tree.loader.insert_randomizer(hash, entry);
+            }
+        }
+
+        // Mount the files of the mods distributed as archives. Files that were discovered in a directory take priority.
+        for entry in std::mem::take(&mut *DISCOVERED_ZIP_ENTRIES.lock().unwrap()) {
+            let hash = match entry.local.smash_hash() {
+                Ok(hash) => hash,
+                Err(e) => {
+                    error!("Could not add file {} to API tree. Reason: {:?}", entry.local.display(), e);
+                    continue;
+                },
+            };
+
+            if hashed_paths.contains_key(&hash) {
+                warn!(
+                    "File '{}' from archive '{}' was rejected because another mod already provides it.",
+                    entry.local.display(),
+                    entry.archive.display()
+                );
+                continue;
+            }
+
+            if let Some(hash) = utils::add_file_to_api_tree(&mut api_tree, &utils::api_root("zip"), &entry.local, ApiCallback::None) {
+                if let Some(string) = entry.local.to_str() {
+                    hashes::add(string);
+                }
+
+                hashed_paths.insert(hash, entry.local.clone());
+                hashed_sizes.insert(hash, entry.size);
+                api_tree.loader.insert_zip_entry(hash, entry);
+            }
+        }
+
+        // Manual overrides are explicitly requested by the user, so they take priority over every mod
+        for (local, physical) in overrides::load() {
+            let size = match std::fs::metadata(&physical) {
+                Ok(metadata) => metadata.len() as usize,
+                Err(e) => {
+                    warn!("Failed to read the size of manual override '{}'. Reason: {:?}", physical.display(), e);
+                    continue;
+                },
+            };
+
+            if let Some(hash) = utils::add_file_to_api_tree(&mut api_tree, &utils::api_root("manual-override"), &local, ApiCallback::None) {
+                if let Some(string) = local.to_str() {
+                    hashes::add(string);
+                }
+
+                if hashed_paths.contains_key(&hash) {
+                    info!("File '{}' is provided by a mod, but is manually overridden with '{}'.", local.display(), physical.display());
+                }
+
+                hashed_paths.insert(hash, local);
+                hashed_sizes.insert(hash, size);
+                api_tree.loader.insert_manual_override(hash, physical);
+            }
+        }
+
+        // Lock the pending callbacks and take them out of the queue. The lock is held until the initialized flag is set,
+        // otherwise a call submitted in between would be queued after the queue was emptied and never be handled.
         let mut pending_calls = api::PENDING_CALLBACKS.lock().unwrap();
-        let mut calls = Vec::new();
-        std::mem::swap(&mut *pending_calls, &mut calls);
-        drop(pending_calls);
+        let calls = std::mem::take(&mut *pending_calls);
 
         // Go through each API call, insert it into the api tree, and then insert it's info into the global data
-        for call in calls {
+        for call in calls.iter().copied() {
             let ApiCallResult { hash, path, size } = Self::handle_panding_api_call(&mut api_tree, call);
 
+            // The path and the size always have to describe the same file, so the size of the file this call replaces can't be kept
             hashed_paths.insert(hash, path);
-            if let Some(size) = size {
-                hashed_sizes.insert(hash, size);
+            match size {
+                Some(size) => {
+                    hashed_sizes.insert(hash, size);
+                },
+                None => {
+                    hashed_sizes.remove(&hash);
+                },
             }
         }
 
         // Set the global flag that we are initialized (referenced by API)
         IS_INIT.store(true, Ordering::SeqCst);
+        drop(pending_calls);
 
         // Construct a CachedFilesystem
         CachedFilesystem {
@@ -273,15 +418,29 @@ impl CachedFilesystem {
             current_nus3bank_id: 7420,
             nus3banks: HashMap::new(),
             total_size: 0,
+            api_calls: calls,
+            vanilla_sizes: HashMap::new(),
+            file_cache: Mutex::new(FileCache::new(config::file_cache_size())),
+            manual_overrides: HashMap::new(),
+            patched_sizes: HashMap::new(),
+            direct_read_threshold: config::direct_read_threshold(),
+            rejected_files,
         }
     }
 
     /// Patches a file in the LoadedArc
-    fn patch_file(&self, hash: Hash40, size: usize) -> Option<usize> {
+    ///
+    /// The file datas belong to the game rather than to the filesystem, so taking `&mut self` does not make this write exclusive:
+    /// the game reads them from its own threads while loading. Changing the size of a file is only sound while the game is not loading it.
+    /// This holds at boot, where every size is patched before the game loads anything. Afterwards it is up to the callers, since sizes
+    /// changed through the API or a rescan must not belong to a file that is being loaded at the time. The API functions that can end up here,
+    /// such as `arcrop_register_callback` and `arcrop_add_manual_override`, pass this requirement on to plugins in their documentation.
+    fn patch_file(&mut self, hash: Hash40, size: usize) -> Option<usize> {
+        // SAFETY: the game is not loading this file, see above. The reference must not outlive this call.
         let arc = resource::arc_mut();
         let region = config::region();
-        let decomp_size = match arc.get_file_data_from_hash(hash, region) {
-            Ok(data) => data.decomp_size as usize,
+        let (decomp_size, is_compressed) = match arc.get_file_data_from_hash(hash, region) {
+            Ok(data) => (data.decomp_size as usize, data.flags.compressed()),
             Err(_) => {
                 warn!(
                     "Failed to patch '{}' ({:#x}) filesize! It should be {:#x}.",
@@ -307,11 +466,37 @@ impl CachedFilesystem {
                 },
                 Err(_) => None,
             }
+        } else if size < decomp_size && size != 0 && config::shrink_files() {
+            // The game decompresses the vanilla file in the buffer before it gets replaced, so it can't be any smaller
+            if is_compressed {
+                return None;
+            }
+
+            match arc.resize_filedata(hash, size as u32, region) {
+                Ok(old_size) => {
+                    info!(
+                        "File '{}' ({:#x}) has a smaller decompressed filesize! {:#x} -> {:#x}",
+                        hashes::find(hash).bright_yellow(),
+                        hash.0,
+                        old_size.red(),
+                        size.green()
+                    );
+                    Some(old_size as usize)
+                },
+                Err(_) => None,
+            }
         } else {
             None
         }
     }
 
+    /// Gives a file the size it had before being patched, under the same rules as `patch_file`
+    fn restore_filesize(&mut self, hash: Hash40, size: usize) -> bool {
+        // SAFETY: the game is not loading this file, under the same rules as `patch_file`
+        let arc = resource::arc_mut();
+        arc.resize_filedata(hash, size as u32, config::region()).is_ok()
+    }
+
     // Search the provided hash for a PathBuf in the hash lookup
     pub fn local_hash(&self, hash: Hash40) -> Option<&PathBuf> {
         self.hash_lookup.get(&hash)
@@ -322,6 +507,34 @@ impl CachedFilesystem {
         self.local_hash(hash).and_then(|x| self.loader.query_actual_path(x))
     }
 
+    // Get the path on the SD card of the file that is loaded for a hash, which files provided by the API don't have
+    pub fn physical_path(&self, hash: Hash40) -> Option<PathBuf> {
+        let local = self.local_hash(hash)?;
+
+        // Manual overrides are mounted in the API tree, but they still point to a file on the SD card
+        if let Ok(hash) = local.smash_hash() {
+            let api = &self.loader.virt().loader;
+
+            if api.is_virtual_file(hash) {
+                return api.current_manual_override(hash).cloned();
+            }
+        }
+
+        self.loader.query_actual_path(local).filter(|path| !path.starts_with(&*utils::API_PREFIX))
+    }
+
+    // Get the path on the SD card of the file the last load of a hash was served from. Unlike the physical path, this includes the file
+    // picked by a randomizer. Files built by the API, such as the ones provided by callbacks, archives or patches, don't have one.
+    pub fn loaded_path(&self, hash: Hash40) -> Option<PathBuf> {
+        let local = self.local_hash(hash)?;
+
+        if let Some(path) = local.smash_hash().ok().and_then(|hash| self.loader.virt().loader.current_randomizer_pick(hash)) {
+            return Some(path.to_path_buf());
+        }
+
+        self.physical_path(hash)
+    }
+
     // Load the file data from the Orbits filesystem
     pub fn load(&self, hash: Hash40) -> Option<Vec<u8>> {
         let path = if let Some(path) = self.hash_lookup.get(&hash) {
@@ -335,7 +548,17 @@ impl CachedFilesystem {
             return None;
         };
 
-        match self.loader.load(path) {
+        // Files provided through the API can change from one load to the next, so only the ones read from mods or manual overrides are cached
+        let is_cacheable = path.smash_hash().map_or(false, |hash| self.is_read_from_sd(hash));
+
+        if is_cacheable {
+            if let Some(data) = self.file_cache.lock().unwrap().get(hash) {
+                return Some(data);
+            }
+        }
+
+        let data = match self.loader.load(path) {
+            Ok(data) if path.has_extension("zst") => utils::decompress_zstd(data),
             Ok(data) => Some(data),
             Err(Error::Virtual(ApiLoaderError::NoVirtFile)) => {
                 if let Ok(data) = self.loader.load_patch(path) {
@@ -351,11 +574,88 @@ impl CachedFilesystem {
                 error!("Failed to load data for {}. Reason: {:?}", path.display(), e);
                 None
             },
+        };
+
+        if is_cacheable {
+            if let Some(data) = data.as_ref() {
+                self.file_cache.lock().unwrap().insert(hash, data);
+            }
+        }
+
+        data
+    }
+
+    /// Checks if a file can be read ahead of time, which is only the case for files provided by mods or manual overrides
+    pub fn is_prefetchable(&self, hash: Hash40) -> bool {
+        self.local_hash(hash)
+            .and_then(|local| local.smash_hash().ok())
+            .map_or(false, |hash| self.is_read_from_sd(hash))
+    }
+
+    // Checks if the file with the hash of a local path is read as is from the SD card, rather than provided by the API
+    fn is_read_from_sd(&self, hash: Hash40) -> bool {
+        let api = &self.loader.virt().loader;
+        !api.is_virtual_file(hash) || api.current_manual_override(hash).is_some()
+    }
+
+    /// Keeps a file that was read ahead of time in memory, unless the hash is served from another file since then
+    pub fn cache_prefetched(&self, hash: Hash40, path: &Path, data: &[u8]) -> bool {
+        if self.physical_path(hash).as_deref() != Some(path) {
+            return false;
+        }
+
+        self.file_cache.lock().unwrap().insert(hash, data);
+        true
+    }
+
+    /// Drops every file kept in memory, so that the next loads are read from the SD card again
+    pub fn clear_cache(&self) {
+        self.file_cache.lock().unwrap().clear();
+    }
+
+    /// Reads a large file provided by a mod straight into the buffer of the game, so that it never has to be copied on the heap.
+    /// Horizon does not let us map files from the SD card into memory, so this is the closest we can get to it.
+    fn load_direct(&self, hash: Hash40, buffer: &mut [u8]) -> Option<usize> {
+        if self.direct_read_threshold == 0 {
+            return None;
+        }
+
+        // Compressed files have to be decompressed into a separate buffer anyway
+        let path = self.physical_path(hash).filter(|path| !path.has_extension("zst"))?;
+
+        // A prefetched file is already in memory, so there is no need to read it again
+        if let Some(data) = self.file_cache.lock().unwrap().get(hash) {
+            if data.len() > buffer.len() {
+                return None;
+            }
+
+            buffer[..data.len()].copy_from_slice(&data);
+            return Some(data.len());
+        }
+
+        let mut file = std::fs::File::open(&path).ok()?;
+        let size = file.metadata().ok()?.len() as usize;
+
+        // Let the regular path take care of small files and report files that do not fit
+        if size < self.direct_read_threshold || size > buffer.len() {
+            return None;
+        }
+
+        match file.read_exact(&mut buffer[..size]) {
+            Ok(_) => Some(size),
+            Err(e) => {
+                error!("Failed to read {} into the game's buffer. Reason: {:?}", path.display(), e);
+                None
+            },
         }
     }
 
     // Load the file data from the Orbits filesystem into a pre-allocated buffer
     pub fn load_into(&self, hash: Hash40, mut buffer: &mut [u8]) -> Option<usize> {
+        if let Some(size) = self.load_direct(hash, buffer) {
+            return Some(size);
+        }
+
         if let Some(data) = self.load(hash) {
             if buffer.len() < data.len() {
                 error!(
@@ -397,14 +697,10 @@ impl CachedFilesystem {
 
     // Subtracts the amount of bytes remanining from the current load.
     // This prevents multiloads on the same file
+    // The hash is returned exactly once, as soon as the reads add up to at least the size of the file. Reads that happen
+    // afterwards (or without any incoming file) leave the state untouched, so that they are never mistaken for another load.
     pub fn sub_remaining_bytes(&mut self, count: usize) -> Option<Hash40> {
-        if count >= self.bytes_remaining {
-            self.bytes_remaining = 0;
-            self.get_incoming()
-        } else {
-            self.bytes_remaining -= count;
-            None
-        }
+        sub_incoming_bytes(&mut self.incoming_load, &mut self.bytes_remaining, count)
     }
 
     // Patch all files in the hash size cache
@@ -414,8 +710,11 @@ impl CachedFilesystem {
         std::mem::swap(&mut hash_cache, &mut self.hash_size_cache);
         for (hash, size) in hash_cache.iter_mut() {
             sum_size += *size;
+            self.patched_sizes.insert(*hash, *size);
             if let Some(old_size) = self.patch_file(*hash, *size) {
-                *size = old_size;
+                self.vanilla_sizes.entry(*hash).or_insert(old_size);
+                // The game reads the smaller of both sizes: the vanilla one for files that grew, the patched one for files that shrunk
+                *size = (*size).min(old_size);
             }
         }
         self.hash_size_cache = hash_cache;
@@ -468,11 +767,17 @@ impl CachedFilesystem {
         }
 
         // Go through and add any files that were not found in the data.arc
+        let mut added_files = 0;
+
         self.loader.walk_patch(|node, ty| {
             if node.get_local().is_stream() || !ty.is_file() {
                 return;
             }
 
+            if self.rejected_files.contains::<Path>(node.full_path().as_ref()) || randomizer::is_candidate(node.get_local()) {
+                return;
+            }
+
             let _hash = if let Ok(hash) = node.get_local().smash_hash() {
                 if context.contains_file(hash) {
                     return;
@@ -482,10 +787,33 @@ impl CachedFilesystem {
                 return;
             };
 
+            if node.get_local().has_extension("zst") {
+                warn!(
+                    "Compressed file '{}' does not replace a file in the data.arc. Compressed files cannot be added as new files.",
+                    node.get_local().display()
+                );
+                return;
+            }
+
             replacement::addition::add_file(&mut context, node.get_local());
             replacement::addition::add_searchable_file_recursive(&mut search_context, node.get_local());
+            added_files += 1;
         });
 
+        // Files from archives live in the API tree, so they have to be added separately
+        for entry in self.loader.virt().loader.zip_entries() {
+            if entry.local.smash_hash().map_or(true, |hash| context.contains_file(hash)) {
+                continue;
+            }
+
+            replacement::addition::add_file(&mut context, &entry.local);
+            replacement::addition::add_searchable_file_recursive(&mut search_context, &entry.local);
+            added_files += 1;
+        }
+
+        // Lets mod authors confirm that their new files were recognized as such, instead of looking like they were ignored
+        info!("Added {} file(s) that are not part of the data.arc.", added_files);
+
         // Don't unshare any files in the unshare blacklist (nus3audio handled during filesystem finish)
         let files = self.hash_lookup.iter().filter_map(
             |(hash, _path)| {
@@ -535,6 +863,108 @@ impl CachedFilesystem {
         resource::search_mut().take_context(search_context);
     }
 
+    /// Logs what loading the mods would change in the data.arc, then forgets about them so that the game is left untouched.
+    /// Every file is listed at the debug level, followed by a summary of how many files would be replaced, resized and added.
+    pub fn dry_run(&mut self) {
+        let arc = resource::arc();
+        let region = config::region();
+        let shrink_files = config::shrink_files();
+
+        let mut replaced = 0;
+        let mut resized = 0;
+        let mut added = 0;
+
+        let mut files: Vec<_> = self.hash_lookup.iter().collect();
+        files.sort_by(|(_, a), (_, b)| a.cmp(b));
+
+        for (hash, path) in files {
+            let size = self.hash_size_cache.get(hash).copied().unwrap_or(0);
+
+            match arc.get_file_data_from_hash(*hash, region) {
+                Ok(data) => {
+                    let decomp_size = data.decomp_size as usize;
+                    replaced += 1;
+
+                    if size > decomp_size || (shrink_files && size != 0 && size < decomp_size && !data.flags.compressed()) {
+                        resized += 1;
+                        debug!("Would replace '{}' and patch its filesize: {:#x} -> {:#x}", path.display(), decomp_size, size);
+                    } else {
+                        debug!("Would replace '{}'", path.display());
+                    }
+                },
+                Err(_) => {
+                    added += 1;
+                    debug!("Would add '{}' to the data.arc", path.display());
+                },
+            }
+        }
+
+        info!(
+            "Dry run: {} file(s) would be replaced ({} with a new filesize) and {} file(s) would be added. Nothing was changed.",
+            replaced, resized, added
+        );
+
+        // Without any file to provide, the hooks leave every load to the game
+        self.hash_lookup.clear();
+        self.hash_size_cache.clear();
+    }
+
+    /// Checks the files of mods against the sizes they were patched for, returning the hash, current size and decompressed size in
+    /// the data.arc of every file that changed on the SD card since, or that no longer fits in the size the game expects.
+    /// This only reads the tables, so it can be called at any time to find files that were edited without rescanning.
+    pub fn verify_patches(&self) -> Vec<(Hash40, u32, u32)> {
+        let arc = resource::arc();
+        let region = config::region();
+
+        let mut mismatches = Vec::new();
+
+        for (hash, patched_size) in self.patched_sizes.iter() {
+            // Files provided through the API don't have a size to check on the SD card
+            let path = if let Some(path) = self.physical_path(*hash) {
+                path
+            } else {
+                continue;
+            };
+
+            let current_size = if path.has_extension("zst") {
+                utils::get_zstd_content_size(&path)
+            } else {
+                std::fs::metadata(&path).ok().map(|metadata| metadata.len() as usize)
+            };
+
+            let current_size = if let Some(size) = current_size {
+                size
+            } else {
+                warn!("Failed to read the size of {} while verifying the patched files.", path.display());
+                continue;
+            };
+
+            let decomp_size = match arc.get_file_data_from_hash(*hash, region) {
+                Ok(data) => data.decomp_size as usize,
+                Err(_) => continue,
+            };
+
+            if current_size != *patched_size || current_size > decomp_size {
+                warn!(
+                    "File {} is {:#x} bytes, but was patched for {:#x} bytes and the game expects at most {:#x} bytes.",
+                    path.display(),
+                    current_size,
+                    patched_size,
+                    decomp_size
+                );
+                mismatches.push((*hash, current_size as u32, decomp_size as u32));
+            }
+        }
+
+        info!(
+            "Verified {} patched file(s), {} of them no longer match their patch.",
+            self.patched_sizes.len(),
+            mismatches.len()
+        );
+
+        mismatches
+    }
+
     /// Gets the global mod config
     pub fn config(&self) -> &ModConfig {
         &self.config
@@ -542,20 +972,207 @@ impl CachedFilesystem {
 
     /// Handles late API calls
     pub fn handle_late_api_call(&mut self, call: api::PendingApiCall) {
+        if config::dry_run() {
+            info!("Dry run: ignoring a file registered through the API.");
+            return;
+        }
+
+        self.api_calls.push(call);
+
         let ApiCallResult { hash, path, size } = Self::handle_panding_api_call(self.loader.virt_mut(), call);
 
+        // The file is now provided by the API, and what was cached for it must not be used anymore
+        self.file_cache.get_mut().unwrap().remove(hash);
+
         self.hash_lookup.insert(hash, path);
+
+        // Same as when building the filesystem, a size that belonged to the replaced file must not outlive it
+        if size.is_none() {
+            self.hash_size_cache.remove(&hash);
+        }
+
         if let Some(size) = size {
-            if let Some(old_size) = self.patch_file(hash, size) {
-                if let Some(size_mut) = self.hash_size_cache.get_mut(&hash) {
-                    if *size_mut > old_size {
-                        *size_mut = old_size;
-                    }
-                } else {
-                    self.hash_size_cache.insert(hash, size);
+            self.patch_late_file(hash, size);
+        }
+    }
+
+    /// Patches the size of a file that started being provided after the filesystem was built
+    fn patch_late_file(&mut self, hash: Hash40, size: usize) {
+        self.patched_sizes.insert(hash, size);
+
+        if let Some(old_size) = self.patch_file(hash, size) {
+            self.vanilla_sizes.entry(hash).or_insert(old_size);
+
+            let incoming_size = size.min(old_size);
+
+            if let Some(size_mut) = self.hash_size_cache.get_mut(&hash) {
+                if *size_mut > incoming_size {
+                    *size_mut = incoming_size;
                 }
+            } else {
+                self.hash_size_cache.insert(hash, incoming_size);
+            }
+        }
+    }
+
+    /// Makes the game load a file from any path on the SD card in place of the file it would usually load, until it gets removed.
+    /// The override takes priority over the files of mods and is kept when rescanning.
+    pub fn add_manual_override(&mut self, hash: Hash40, path: PathBuf) -> bool {
+        if config::dry_run() {
+            info!("Dry run: ignoring a manual override added through the API.");
+            return false;
+        }
+
+        let size = match std::fs::metadata(&path) {
+            Ok(metadata) if metadata.is_file() => metadata.len() as usize,
+            _ => {
+                warn!("Manual override for '{}' points to '{}', which is not a file.", hashes::find(hash), path.display());
+                return false;
+            },
+        };
+
+        // Overriding the same file again replaces the previous override
+        self.remove_manual_override(hash);
+
+        let local = get_path_from_hash(hash);
+
+        let local_hash = match local.smash_hash() {
+            Ok(local_hash) => local_hash,
+            Err(e) => {
+                error!("Could not add file {} to API tree. Reason: {:?}", local.display(), e);
+                return false;
+            },
+        };
+
+        // A file provided by a mod has to be served again once the override is removed
+        let base = self.physical_path(hash).filter(|_| !self.loader.virt().loader.is_virtual_file(local_hash));
+
+        let entry = ManualOverride {
+            path: path.clone(),
+            previous_local: self.hash_lookup.get(&hash).cloned(),
+            previous_cached_size: self.hash_size_cache.get(&hash).copied(),
+            previous_patched_size: self.patched_sizes.get(&hash).copied(),
+            previous_filesize: resource::arc()
+                .get_file_data_from_hash(hash, config::region())
+                .ok()
+                .map(|data| data.decomp_size as usize),
+        };
+
+        let root = utils::api_root("manual-override");
+        let api_tree = self.loader.virt_mut();
+        api_tree.insert_file(&root, &local);
+        api_tree.loader.mount_manual_override(local_hash, &root, path, base);
+
+        self.file_cache.get_mut().unwrap().remove(hash);
+
+        self.hash_lookup.insert(hash, local);
+        self.manual_overrides.insert(hash, entry);
+
+        self.patch_late_file(hash, size);
+
+        true
+    }
+
+    /// Removes an override added through `add_manual_override`, so that the file is loaded from wherever it was before
+    /// and gets back the size it had then
+    pub fn remove_manual_override(&mut self, hash: Hash40) -> bool {
+        let entry = if let Some(entry) = self.manual_overrides.remove(&hash) {
+            entry
+        } else {
+            return false;
+        };
+
+        let local = self.hash_lookup.get(&hash).cloned().unwrap_or_else(|| get_path_from_hash(hash));
+
+        if let Ok(local_hash) = local.smash_hash() {
+            self.loader
+                .virt_mut()
+                .loader
+                .unmount_manual_override(local_hash, &utils::api_root("manual-override"), &entry.path);
+        }
+
+        self.file_cache.get_mut().unwrap().remove(hash);
+
+        // A file that was not modded before the override is not modded anymore, and its vanilla size is the one to go back to
+        if entry.previous_local.is_none() {
+            self.vanilla_sizes.remove(&hash);
+        }
+
+        restore_entry(&mut self.hash_lookup, hash, entry.previous_local);
+        restore_entry(&mut self.hash_size_cache, hash, entry.previous_cached_size);
+        restore_entry(&mut self.patched_sizes, hash, entry.previous_patched_size);
+
+        if let Some(size) = entry.previous_filesize {
+            if !self.restore_filesize(hash, size) {
+                warn!("Failed to restore the filesize of '{}' ({:#x}).", hashes::find(hash).bright_yellow(), hash.0);
+            }
+        }
+
+        true
+    }
+
+    /// Discovers the mods again and rebuilds the filesystem from them, so that mods can be changed without restarting the game.
+    /// Callbacks registered through the API are kept, and files that are no longer provided by any mod get their vanilla size back.
+    /// Adding new files to the data.arc and unsharing are not performed again, so mods relying on either still require a restart.
+    /// If the active preset can't be read, nothing is rebuilt and the current mods stay loaded.
+    ///
+    /// The caller holds the write lock of `GLOBAL_FILESYSTEM` for the entire discovery, which stalls every loading hook until it is done.
+    pub fn rescan(&mut self) {
+        info!("Rescanning the mods directory...");
+
+        discover::register_new_mods();
+
+        let launchpad = match discover::discover_mods() {
+            Ok(launchpad) => launchpad,
+            Err(e) => {
+                warn!("Failed to read the active preset, the current mods are kept. Reason: {:?}", e);
+                return;
+            },
+        };
+
+        let mut filesystem = Self::make_from_promise(launchpad);
+
+        if config::dry_run() {
+            filesystem.dry_run();
+            return;
+        }
+
+        filesystem.reshare_files();
+
+        // Files that are still modded have their vanilla size recorded here and not in the size of their previous patch
+        filesystem.vanilla_sizes = self.vanilla_sizes.clone();
+        filesystem.patch_files();
+
+        for call in self.api_calls.iter().copied() {
+            filesystem.handle_late_api_call(call);
+        }
+
+        for (hash, entry) in self.manual_overrides.iter() {
+            filesystem.add_manual_override(*hash, entry.path.clone());
+        }
+
+        for (hash, size) in self.vanilla_sizes.iter() {
+            if filesystem.hash_size_cache.contains_key(hash) || filesystem.hash_lookup.contains_key(hash) {
+                continue;
             }
+
+            if !filesystem.restore_filesize(*hash, *size) {
+                warn!("Failed to restore the filesize of '{}' ({:#x}).", hashes::find(*hash).bright_yellow(), hash.0);
+            }
+
+            filesystem.vanilla_sizes.remove(hash);
         }
+
+        // The game might be holding on to these
+        filesystem.incoming_load = self.incoming_load.take();
+        filesystem.bytes_remaining = self.bytes_remaining;
+        filesystem.current_nus3bank_id = self.current_nus3bank_id;
+        filesystem.nus3banks = std::mem::take(&mut self.nus3banks);
+
+        // The new filesystem starts with an empty file cache, so nothing that changed on the SD card is served from memory
+        *self = filesystem;
+
+        info!("Finished rescanning the mods directory.");
     }
 
     /// Gets the cached size
@@ -630,6 +1247,20 @@ impl GlobalFilesystem {
         }
     }
 
+    pub fn physical_path(&self, hash: Hash40) -> Option<PathBuf> {
+        match self {
+            Self::Initialized(fs) => fs.physical_path(hash),
+            _ => None,
+        }
+    }
+
+    pub fn loaded_path(&self, hash: Hash40) -> Option<PathBuf> {
+        match self {
+            Self::Initialized(fs) => fs.loaded_path(hash),
+            _ => None,
+        }
+    }
+
     pub fn load_into(&self, hash: Hash40, buffer: &mut [u8]) -> Option<usize> {
         match self {
             Self::Initialized(fs) => fs.load_into(hash, buffer),
@@ -693,6 +1324,13 @@ impl GlobalFilesystem {
         }
     }
 
+    pub fn dry_run(&mut self) {
+        match self {
+            Self::Initialized(fs) => fs.dry_run(),
+            _ => error!("Cannot perform a dry run because the filesystem is not initialized!"),
+        }
+    }
+
     pub fn share_hashes(&mut self) {
         match self {
             Self::Initialized(fs) => fs.reshare_files(),
@@ -741,6 +1379,64 @@ impl GlobalFilesystem {
         }
     }
 
+    pub fn is_prefetchable(&self, hash: Hash40) -> bool {
+        match self {
+            Self::Initialized(fs) => fs.is_prefetchable(hash),
+            _ => false,
+        }
+    }
+
+    pub fn cache_prefetched(&self, hash: Hash40, path: &Path, data: &[u8]) -> bool {
+        match self {
+            Self::Initialized(fs) => fs.cache_prefetched(hash, path, data),
+            _ => false,
+        }
+    }
+
+    pub fn clear_cache(&self) {
+        match self {
+            Self::Initialized(fs) => fs.clear_cache(),
+            _ => error!("Cannot clear the file cache because the filesystem is not initialized!"),
+        }
+    }
+
+    pub fn verify_patches(&self) -> Vec<(Hash40, u32, u32)> {
+        match self {
+            Self::Initialized(fs) => fs.verify_patches(),
+            _ => {
+                error!("Cannot verify the patched files because the filesystem is not initialized!");
+                Vec::new()
+            },
+        }
+    }
+
+    pub fn add_manual_override(&mut self, hash: Hash40, path: PathBuf) -> bool {
+        match self {
+            Self::Initialized(fs) => fs.add_manual_override(hash, path),
+            _ => {
+                error!("Cannot add a manual override because the filesystem is not initialized!");
+                false
+            },
+        }
+    }
+
+    pub fn remove_manual_override(&mut self, hash: Hash40) -> bool {
+        match self {
+            Self::Initialized(fs) => fs.remove_manual_override(hash),
+            _ => {
+                error!("Cannot remove a manual override because the filesystem is not initialized!");
+                false
+            },
+        }
+    }
+
+    pub fn rescan(&mut self) {
+        match self {
+            Self::Initialized(fs) => fs.rescan(),
+            _ => error!("Cannot rescan the mods because the filesystem is not initialized!"),
+        }
+    }
+
     pub fn get_cached_size(&self, hash: Hash40) -> Option<usize> {
         match self {
             Self::Initialized(fs) => fs.get_cached_size(hash),
@@ -755,3 +1451,91 @@ impl GlobalFilesystem {
         }
     }
 }
+
+/// Runs the closure with the local path of a file while the filesystem is locked for reading, releasing the lock before returning.
+/// Prefer this over holding a guard on the filesystem, which deadlocks as soon as something needs to write to it.
+pub fn with_local_path<R, F: FnOnce(&Path) -> R>(hash: Hash40, f: F) -> Option<R> {
+    let filesystem = unsafe { crate::GLOBAL_FILESYSTEM.read().unwrap() };
+    filesystem.local_hash(hash).map(|path| f(path))
+}
+
+/// Gets a copy of the local path of a file, so that the filesystem doesn't stay locked while using it
+pub fn get_local_path(hash: Hash40) -> Option<PathBuf> {
+    with_local_path(hash, Path::to_path_buf)
+}
+
+/// Gets the path on the SD card of the file that is loaded for a hash, without keeping the filesystem locked while using it
+pub fn get_physical_path(hash: Hash40) -> Option<PathBuf> {
+    unsafe { crate::GLOBAL_FILESYSTEM.read().unwrap().physical_path(hash) }
+}
+
+/// Gets the path on the SD card that the last load of a hash was served from, without keeping the filesystem locked while using it
+pub fn get_loaded_path(hash: Hash40) -> Option<PathBuf> {
+    unsafe { crate::GLOBAL_FILESYSTEM.read().unwrap().loaded_path(hash) }
+}
+
+// Puts back the value a map had for a key, including the lack of one
+fn restore_entry<V>(map: &mut HashMap<Hash40, V>, key: Hash40, value: Option<V>) {
+    match value {
+        Some(value) => {
+            map.insert(key, value);
+        },
+        None => {
+            map.remove(&key);
+        },
+    }
+}
+
+// Kept apart from the filesystem so that the byte counting can be tested without a data.arc
+fn sub_incoming_bytes(incoming_load: &mut Option<Hash40>, bytes_remaining: &mut usize, count: usize) -> Option<Hash40> {
+    incoming_load.as_ref()?;
+
+    *bytes_remaining = bytes_remaining.saturating_sub(count);
+
+    if *bytes_remaining == 0 {
+        incoming_load.take()
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: Hash40 = Hash40(0x1234_5678_9a);
+
+    #[test]
+    fn exact_read_completes_the_load() {
+        let (mut incoming, mut remaining) = (Some(HASH), 0x100);
+
+        assert_eq!(sub_incoming_bytes(&mut incoming, &mut remaining, 0x100), Some(HASH));
+        assert_eq!(incoming, None);
+        assert_eq!(remaining, 0);
+    }
+
+    #[test]
+    fn over_read_completes_the_load_once() {
+        let (mut incoming, mut remaining) = (Some(HASH), 0x100);
+
+        assert_eq!(sub_incoming_bytes(&mut incoming, &mut remaining, 0x104), Some(HASH));
+        assert_eq!(remaining, 0);
+
+        // A stray read afterwards must not report the load again
+        assert_eq!(sub_incoming_bytes(&mut incoming, &mut remaining, 0x10), None);
+        assert_eq!(sub_incoming_bytes(&mut incoming, &mut remaining, 0), None);
+    }
+
+    #[test]
+    fn chunked_reads_complete_the_load_on_the_last_chunk() {
+        let (mut incoming, mut remaining) = (Some(HASH), 0x100);
+
+        for _ in 0..3 {
+            assert_eq!(sub_incoming_bytes(&mut incoming, &mut remaining, 0x40), None);
+        }
+
+        assert_eq!(remaining, 0x40);
+        assert_eq!(sub_incoming_bytes(&mut incoming, &mut remaining, 0x40), Some(HASH));
+        assert_eq!(incoming, None);
+    }
+}
@@ -0,0 +1,75 @@
+use std::collections::{HashMap, VecDeque};
+
+use smash_arc::Hash40;
+
+/// Keeps the contents of recently loaded files in memory, evicting the least recently used ones once the byte budget is exceeded
+pub struct FileCache {
+    budget: usize,
+    used: usize,
+    entries: HashMap<Hash40, Vec<u8>>,
+    // Least recently used hash first
+    order: VecDeque<Hash40>,
+}
+
+impl FileCache {
+    /// Creates a cache that holds at most `budget` bytes of file data. A budget of 0 disables caching.
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            used: 0,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    pub fn get(&mut self, hash: Hash40) -> Option<Vec<u8>> {
+        let data = self.entries.get(&hash)?.clone();
+        self.touch(hash);
+        Some(data)
+    }
+
+    pub fn insert(&mut self, hash: Hash40, data: &[u8]) {
+        // Don't throw away the entire cache for a single file that could never fit in it
+        if data.len() > self.budget {
+            return;
+        }
+
+        self.remove(hash);
+
+        while self.used + data.len() > self.budget {
+            match self.order.pop_front() {
+                Some(evicted) => {
+                    if let Some(evicted) = self.entries.remove(&evicted) {
+                        self.used -= evicted.len();
+                    }
+                },
+                None => break,
+            }
+        }
+
+        self.used += data.len();
+        self.entries.insert(hash, data.to_vec());
+        self.order.push_back(hash);
+    }
+
+    pub fn remove(&mut self, hash: Hash40) {
+        if let Some(data) = self.entries.remove(&hash) {
+            self.used -= data.len();
+            self.order.retain(|x| *x != hash);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.used = 0;
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    // Moves the hash to the back of the queue, making it the last one to be evicted
+    fn touch(&mut self, hash: Hash40) {
+        if let Some(position) = self.order.iter().position(|x| *x == hash) {
+            self.order.remove(position);
+        }
+        self.order.push_back(hash);
+    }
+}
@@ -1,27 +1,166 @@
 use std::{
+    cell::{Cell, RefCell},
     collections::{HashMap, HashSet},
-    path::{Path, PathBuf},
+    fs::File,
+    io::{BufReader, Read, Seek},
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, LazyLock, Mutex,
+    },
+    time::Instant,
 };
 
-use camino::Utf8Path;
+use camino::{Utf8Path, Utf8PathBuf};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::Serialize;
 use orbits::{ConflictHandler, ConflictKind, FileLoader, LaunchPad, StandardLoader, Tree};
 use skyline::nn::{self, ro::*};
 use smash_arc::Hash40;
+use zip::{result::ZipError, ZipArchive};
 
-use crate::{chainloader::*, utils};
+use super::{manifest, ModInfo, ZipEntry};
+use crate::{chainloader::*, region, utils, PathExtension};
 
-pub fn perform_discovery() -> LaunchPad<StandardLoader> {
-    let is_emulator = utils::env::is_emulator();
+/// Files of the mods distributed as .zip archives, found during discovery and mounted once the filesystem is built
+pub static DISCOVERED_ZIP_ENTRIES: Mutex<Vec<ZipEntry>> = Mutex::new(Vec::new());
+
+/// User-provided patterns for files that should never be discovered, such as READMEs or image sources. Compiled once on first use.
+static DISCOVERY_EXCLUSIONS: LazyLock<GlobSet> = LazyLock::new(|| build_exclusions(&config::discovery_exclusions()));
+
+/// Prefixes and suffixes of the names of the files and directories that are never discovered. Read once on first use.
+static IGNORED_ENTRIES: LazyLock<(Vec<String>, Vec<String>)> = LazyLock::new(|| {
+    let non_empty = |list: Vec<String>| -> Vec<String> { list.into_iter().filter(|entry| !entry.is_empty()).collect() };
+    (non_empty(config::ignored_prefixes()), non_empty(config::ignored_suffixes()))
+});
+
+/// Full paths of the files that did not match the manifest of their mod, so that they are left out when the filesystem is built
+pub static REJECTED_FILES: LazyLock<Mutex<HashSet<PathBuf>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+static LAST_DISCOVERY_STATS: Mutex<DiscoveryStats> = Mutex::new(DiscoveryStats::new());
+
+/// Numbers gathered during the last discovery, so that slow boots can be diagnosed. Shared as is with plugins through the API.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct DiscoveryStats {
+    pub mod_roots: usize,
+    pub skipped_mod_roots: usize,
+    pub directories: usize,
+    pub files: usize,
+    pub archive_files: usize,
+    /// Only known once the filesystem has been built, since the size of every file has to be read for it
+    pub total_size: usize,
+    pub skipped_invalid_name: usize,
+    pub skipped_loose: usize,
+    pub skipped_hidden: usize,
+    pub skipped_out_of_region: usize,
+    pub skipped_excluded: usize,
+    pub elapsed_ms: u64,
+}
+
+impl DiscoveryStats {
+    const fn new() -> Self {
+        Self {
+            mod_roots: 0,
+            skipped_mod_roots: 0,
+            directories: 0,
+            files: 0,
+            archive_files: 0,
+            total_size: 0,
+            skipped_invalid_name: 0,
+            skipped_loose: 0,
+            skipped_hidden: 0,
+            skipped_out_of_region: 0,
+            skipped_excluded: 0,
+            elapsed_ms: 0,
+        }
+    }
+}
+
+// The ignore closure can't borrow anything, so the amount of files it skips is counted here
+struct SkipCounters {
+    invalid_name: AtomicUsize,
+    loose: AtomicUsize,
+    hidden: AtomicUsize,
+    out_of_region: AtomicUsize,
+    excluded: AtomicUsize,
+}
+
+static SKIP_COUNTERS: SkipCounters = SkipCounters {
+    invalid_name: AtomicUsize::new(0),
+    loose: AtomicUsize::new(0),
+    hidden: AtomicUsize::new(0),
+    out_of_region: AtomicUsize::new(0),
+    excluded: AtomicUsize::new(0),
+};
 
-    if is_emulator {
+impl SkipCounters {
+    fn reset(&self) {
+        for counter in [&self.invalid_name, &self.loose, &self.hidden, &self.out_of_region, &self.excluded] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn record(counter: &AtomicUsize) -> bool {
+        counter.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+}
+
+/// Gets the numbers gathered during the last discovery
+pub fn last_discovery_stats() -> DiscoveryStats {
+    *LAST_DISCOVERY_STATS.lock().unwrap()
+}
+
+/// Records the total size of the discovered files, once the filesystem has read them
+pub(super) fn record_discovered_size(total_size: usize) {
+    LAST_DISCOVERY_STATS.lock().unwrap().total_size = total_size;
+    info!("Discovered files have a total size of {:#x} bytes.", total_size);
+}
+
+pub fn perform_discovery() -> LaunchPad<StandardLoader> {
+    if utils::env::is_emulator() {
         info!("Emulator usage detected in perform_discovery, reverting to old behavior.");
     }
 
+    register_new_mods();
+
+    #[cfg(feature = "ui")]
+    crate::check_input_on_boot();
+
+    let launchpad = discover_mods().unwrap_or_else(|e| panic!("Failed to read the active preset. Reason: {:?}", e));
+
+    match mount_prebuilt_nrr(launchpad.tree()) {
+        Ok(Some(_)) => info!("Successfully registered fighter modules."),
+        Ok(_) => info!("No fighter modules found to register."),
+        Err(e) => {
+            error!("{:?}", e);
+            crate::dialog_error(
+                "ARCropolis failed to register module information for fighter modules.<br>You may experience infinite loading on some fighters.",
+            );
+        },
+    }
+
+    load_and_run_plugins(launchpad.collected_paths());
+
+    launchpad
+}
+
+/// Adds the mods that were installed since the last time the mod cache was updated to the active preset, so that they are enabled by default
+pub fn register_new_mods() {
+    let is_emulator = utils::env::is_emulator();
+
     let mods_path = utils::paths::mods();
 
     let legacy_discovery = config::legacy_discovery();
 
-    let mut presets = config::presets::get_active_preset().unwrap();
+    let mut presets = match config::presets::get_active_preset() {
+        Ok(presets) => presets,
+        Err(e) => {
+            warn!("Failed to read the active preset, new mods will not be enabled. Reason: {:?}", e);
+            return;
+        },
+    };
 
     // Emulators can't use presets, so don't run this logic
     if !is_emulator && !legacy_discovery {
@@ -29,18 +168,7 @@ pub fn perform_discovery() -> LaunchPad<StandardLoader> {
         let mod_cache: HashSet<Hash40> = config::get_mod_cache().unwrap_or_default();
 
         // Inspect the list of mods to see if some are new ones
-        let new_cache: HashSet<Hash40> = std::fs::read_dir(&mods_path)
-            .unwrap()
-            .filter_map(|path| {
-                let path = PathBuf::from(&mods_path).join(path.unwrap().path());
-
-                if path.is_file() {
-                    None
-                } else {
-                    Some(Hash40::from(path.to_str().unwrap()))
-                }
-            })
-            .collect();
+        let new_cache = collect_mod_roots(&mods_path);
 
         let new_mods: HashSet<&Hash40> = new_cache
             .iter()
@@ -51,52 +179,164 @@ pub fn perform_discovery() -> LaunchPad<StandardLoader> {
         if !new_mods.is_empty() {
             // Add the new mods to the presets file
             presets.extend(new_mods);
-            // Save it back
-            config::presets::replace_active_preset(&presets).unwrap();
+            // Save it back, and keep the previous cache if that fails so these mods are still considered new next time
+            if let Err(e) = config::presets::replace_active_preset(&presets) {
+                warn!("Failed to enable the new mods in the active preset. Reason: {:?}", e);
+                return;
+            }
         }
 
         // No matter what, the cache has to be updated
-        config::set_mod_cache(&new_cache).unwrap();
+        if let Err(e) = config::set_mod_cache(&new_cache) {
+            warn!("Failed to update the mod cache. Reason: {:?}", e);
+        }
     }
+}
 
-    #[cfg(feature = "ui")]
-    crate::check_input_on_boot();
+/// Hashes the path of every mod root in the mods directory, which is how presets and the mod cache identify them
+fn collect_mod_roots(mods_path: &Utf8Path) -> HashSet<Hash40> {
+    let entries: Vec<_> = match std::fs::read_dir(mods_path) {
+        Ok(entries) => entries.collect(),
+        Err(e) => {
+            warn!("Failed to read the mods directory at '{}' while looking for mod roots. Reason: {:?}", mods_path, e);
+            Vec::new()
+        },
+    };
+
+    entries
+        .into_iter()
+        .filter_map(|entry| {
+            // A single unreadable entry should not prevent the rest of the mods from being cached
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn!("Failed to read an entry of the mods directory, skipping it. Reason: {:?}", e);
+                    return None;
+                },
+            };
+
+            // The path of the entry already includes the mods directory
+            let path = entry.path();
+
+            // Archives are mod roots just like directories are
+            if path.is_file() && !path.has_extension("zip") {
+                return None;
+            }
+
+            match Utf8PathBuf::from_path_buf(path) {
+                Ok(path) => Some(Hash40::from(path.as_str())),
+                Err(path) => {
+                    warn!("Mod directory '{}' does not have a valid UTF-8 name and will be skipped.", path.display());
+                    None
+                },
+            }
+        })
+        .collect()
+}
+
+/// Discovers the files of every enabled mod.
+/// Unlike perform_discovery, this neither registers fighter modules nor runs plugins, so it is safe to call again after boot.
+pub fn discover_mods() -> Result<LaunchPad<StandardLoader>, config::presets::PresetError> {
+    let start = Instant::now();
+
+    SKIP_COUNTERS.reset();
+
+    let is_emulator = utils::env::is_emulator();
+
+    let mods_path = utils::paths::mods();
+
+    let legacy_discovery = config::legacy_discovery();
 
     // If the user edited their mods again, we'll have to reload them here. This is obviously bad and inefficient but it wouldn't be ARCropolis if it wasn't.
     // Consider loading the active presets in a static RwLock so everything can manipulate them without reloading
-    let presets = config::presets::get_active_preset().unwrap();
+    let presets = config::presets::get_active_preset()?;
+
+    // Mods disabled by name in the configuration are never loaded, no matter the discovery method
+    let disabled_mods = config::disabled_mods();
+
+    let mod_infos = load_mod_infos(&mods_path);
+
+    check_mods_directory(&mods_path);
+
+    let check_root = |path: &Path| {
+        // Mod roots are identified by their path, so one that can't be represented as UTF-8 can never be loaded
+        let path = if let Some(path) = Utf8Path::from_path(path) {
+            path
+        } else {
+            warn!("Mod root '{}' does not have a valid UTF-8 path and will be skipped.", path.display());
+            return false;
+        };
+
+        if path.file_name().map(|name| disabled_mods.contains(name)).unwrap_or(false) {
+            info!("Mod root '{}' is disabled in the configuration and will be skipped.", path);
+            return false;
+        }
+
+        if mod_infos.get(path.as_std_path()).map_or(false, |info| info.is_disabled()) {
+            info!("Mod root '{}' is disabled by its info.toml and will be skipped.", path);
+            return false;
+        }
 
-    let filter = |path: &Path| {
         // If we're not running on emulator
         if !is_emulator && !legacy_discovery {
             // If it's not in the presets, don't load
-            presets.contains(&Hash40::from(path.to_str().unwrap()))
+            presets.contains(&Hash40::from(path.as_str()))
         } else {
             // Legacy filter, load the mod except if it has a period at the start of the name
+            path.file_name().map(|name| !is_ignored_entry(name)).unwrap_or(false)
+        }
+    };
 
-            Utf8Path::from_path(path)
-                .unwrap()
-                .file_name()
-                .map(|name| !name.starts_with('.'))
-                .unwrap_or(false)
+    // Every pass over the mods directory goes through the filter again, so each root is only checked, and reported, once
+    let checked_roots: RefCell<HashMap<PathBuf, bool>> = RefCell::new(HashMap::new());
+    let filter = |path: &Path| {
+        if let Some(is_enabled) = checked_roots.borrow().get(path) {
+            return *is_enabled;
         }
+
+        let is_enabled = check_root(path);
+        checked_roots.borrow_mut().insert(path.to_path_buf(), is_enabled);
+        is_enabled
     };
 
+    // Mods can ship a manifest of their files, which are checked before anything gets discovered
+    let (rejected_roots, rejected_files) = verify_manifests(&mods_path, filter);
+    *REJECTED_FILES.lock().unwrap() = rejected_files;
+
+    let rejected_roots = &rejected_roots;
+    let filter = move |path: &Path| !rejected_roots.contains(path) && filter(path);
+
     let ignore = |path: &Path| {
-        let name = if let Some(name) = path.file_name().and_then(|x| x.to_str()) { name } else { return false };
+        let name = match path.file_name().map(|x| x.to_str()) {
+            Some(Some(name)) => name,
+            Some(None) => {
+                // The game only knows about UTF-8 paths, so there is no way this file could be hashed
+                warn!("File '{}' does not have a valid UTF-8 name and will be skipped.", path.display());
+                return SkipCounters::record(&SKIP_COUNTERS.invalid_name);
+            },
+            None => return false,
+        };
 
         let is_root = path.parent().map(|parent| parent.as_os_str().is_empty()).unwrap_or(true);
 
-        let is_dot = name.starts_with('.');
+        let is_hidden = is_ignored_entry(name);
 
-        let is_out_of_region = if let Some(index) = name.find('+') {
-            let (_, end) = name.split_at(index + 1);
-            !end.starts_with(&config::region().to_string())
+        let is_out_of_region = region::is_out_of_region(name, config::asset_region());
+
+        // Matched against the path relative to the mod root, which is the path the file would have in the data.arc
+        let is_excluded = DISCOVERY_EXCLUSIONS.is_match(path);
+
+        if is_root {
+            SkipCounters::record(&SKIP_COUNTERS.loose)
+        } else if is_hidden {
+            SkipCounters::record(&SKIP_COUNTERS.hidden)
+        } else if is_out_of_region {
+            SkipCounters::record(&SKIP_COUNTERS.out_of_region)
+        } else if is_excluded {
+            SkipCounters::record(&SKIP_COUNTERS.excluded)
         } else {
             false
-        };
-
-        is_root || is_dot || is_out_of_region
+        }
     };
 
     let collect = |x: &Path| {
@@ -121,17 +361,12 @@ pub fn perform_discovery() -> LaunchPad<StandardLoader> {
 
                     "motdiff",
 
-                    "yml"
+                    "yml",
+
+                    "jsonx"
                 ];
-                RESERVED_NAMES.contains(&name) || {
-                    let is_out_of_region = if let Some(index) = name.find('+') {
-                        let (_, end) = name.split_at(index + 1);
-                        !end.starts_with(&config::region().to_string())
-                    } else {
-                        false
-                    };
-                    PATCH_EXTENSIONS.iter().any(|x| name.ends_with(x)) && !is_out_of_region
-                }
+                RESERVED_NAMES.contains(&name)
+                    || (PATCH_EXTENSIONS.iter().any(|x| name.ends_with(x)) && !region::is_out_of_region(name, config::asset_region()))
             },
             _ => false
         }
@@ -142,7 +377,31 @@ pub fn perform_discovery() -> LaunchPad<StandardLoader> {
     launchpad.collecting(collect);
     launchpad.ignoring(ignore);
 
-    let conflicts = launchpad.discover_roots(&mods_path, 1, filter);
+    // Only count the roots during the first discovery, since the one reporting conflicts goes over the same roots
+    let roots = Cell::new((0, 0));
+
+    let conflicts = launchpad.discover_roots(&mods_path, 1, |path: &Path| {
+        let is_enabled = filter(path);
+        let (enabled, skipped) = roots.get();
+        roots.set(if is_enabled { (enabled + 1, skipped) } else { (enabled, skipped + 1) });
+        is_enabled
+    });
+
+    let mut stats = DiscoveryStats::new();
+    (stats.mod_roots, stats.skipped_mod_roots) = roots.get();
+    stats.skipped_invalid_name = SKIP_COUNTERS.invalid_name.load(Ordering::Relaxed);
+    stats.skipped_loose = SKIP_COUNTERS.loose.load(Ordering::Relaxed);
+    stats.skipped_hidden = SKIP_COUNTERS.hidden.load(Ordering::Relaxed);
+    stats.skipped_out_of_region = SKIP_COUNTERS.out_of_region.load(Ordering::Relaxed);
+    stats.skipped_excluded = SKIP_COUNTERS.excluded.load(Ordering::Relaxed);
+
+    launchpad.tree().walk_paths(|_, ty| {
+        if ty.is_file() {
+            stats.files += 1;
+        } else {
+            stats.directories += 1;
+        }
+    });
 
     let has_conflicts = !conflicts.is_empty();
 
@@ -178,67 +437,478 @@ pub fn perform_discovery() -> LaunchPad<StandardLoader> {
 
         let conflicts = launchpad.discover_roots(utils::paths::mods(), 1, filter);
 
-        let mut conflict_map: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+        let report = build_conflict_report(conflicts, &mod_infos);
 
-        for conflict in conflicts.into_iter() {
-            if let ConflictKind::StandardConflict {
-                error_root,
-                local,
-                source_root,
-            } = conflict
-            {
-                if let Some(conflicting_mods) = conflict_map.get_mut(&local) {
-                    conflicting_mods.push(error_root);
-                } else {
-                    conflict_map.insert(local, vec![source_root, error_root]);
-                }
-            }
-        }
-
-        let should_log = match serde_json::to_string_pretty(&conflict_map) {
-            Ok(json) => match std::fs::write("sd:/ultimate/arcropolis/conflicts.json", json.as_bytes()) {
-                Ok(_) => {
-                    crate::dialog_error("Conflict file created at sd:/ultimate/arcropolis/conflicts.json. Please open this file in a text editor to preview what mods are conflicting with one another and take the necessary changes to resolve them by either reslotting or removing these mods.");
-                    false
-                },
-                Err(e) => {
-                    crate::dialog_error(format!(
-                        "Failed to write conflict map to sd:/ultimate/arcropolis/conflicts.json<br>{:?}",
-                        e
-                    ));
-                    true
-                },
+        let should_log = match write_conflict_report(&report, Utf8Path::new("sd:/ultimate/arcropolis/conflicts.json")) {
+            Ok(_) => {
+                crate::dialog_error("Conflict file created at sd:/ultimate/arcropolis/conflicts.json. Please open this file in a text editor to preview what mods are conflicting with one another and take the necessary changes to resolve them by either reslotting or removing these mods.");
+                false
             },
             Err(e) => {
-                crate::dialog_error(format!("Failed to serialize conflict map to JSON. {:?}", e));
+                crate::dialog_error(format!(
+                    "Failed to write conflict map to sd:/ultimate/arcropolis/conflicts.json<br>{:?}",
+                    e
+                ));
                 true
             },
         };
 
         if should_log {
-            for (local, roots) in conflict_map {
-                error!("The file {} is used by the following roots:", local.display());
-                for root in roots {
+            for entry in report {
+                error!("The file {} ({}) is used by the following roots:", entry.path.display(), entry.hash);
+                for root in entry.roots {
                     error!("{}", root.display());
                 }
             }
         }
     }
 
-    match mount_prebuilt_nrr(launchpad.tree()) {
-        Ok(Some(_)) => info!("Successfully registered fighter modules."),
-        Ok(_) => info!("No fighter modules found to register."),
+    // Archives can't be part of the discovered tree, so their files are mounted in the API tree when the filesystem is built instead
+    let zip_entries = discover_zip_mods(&mods_path, filter, ignore, collect);
+    stats.archive_files = zip_entries.len();
+    *DISCOVERED_ZIP_ENTRIES.lock().unwrap() = zip_entries;
+
+    let elapsed = start.elapsed();
+    stats.elapsed_ms = elapsed.as_millis() as u64;
+
+    info!(
+        "Discovery finished in {:.2?}. Found {} mod roots ({} skipped), {} directories, {} files and {} files in archives.",
+        elapsed, stats.mod_roots, stats.skipped_mod_roots, stats.directories, stats.files, stats.archive_files
+    );
+    info!(
+        "Skipped {} files with an invalid name, {} files at the root of a mod, {} hidden files, {} files for another region and {} excluded files.",
+        stats.skipped_invalid_name, stats.skipped_loose, stats.skipped_hidden, stats.skipped_out_of_region, stats.skipped_excluded
+    );
+
+    *LAST_DISCOVERY_STATS.lock().unwrap() = stats;
+
+    Ok(launchpad)
+}
+
+/// Reads the info of every mod directory, so that the ones it disables can be skipped
+fn load_mod_infos(mods_path: &Utf8Path) -> HashMap<PathBuf, ModInfo> {
+    match std::fs::read_dir(mods_path) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .map(|path| {
+                let info = ModInfo::from_root(&path);
+                (path, info)
+            })
+            .collect(),
         Err(e) => {
-            error!("{:?}", e);
-            crate::dialog_error(
-                "ARCropolis failed to register module information for fighter modules.<br>You may experience infinite loading on some fighters.",
+            warn!("Failed to read the mods directory at '{}' while reading mod infos. Reason: {:?}", mods_path, e);
+            HashMap::new()
+        },
+    }
+}
+
+/// Checks the manifest of every enabled mod directory. Returns the roots that have to be skipped entirely because strict manifests are enabled,
+/// and the files that have to be skipped otherwise.
+fn verify_manifests<F: Fn(&Path) -> bool>(mods_path: &Utf8Path, filter: F) -> (HashSet<PathBuf>, HashSet<PathBuf>) {
+    let mut rejected_roots = HashSet::new();
+    let mut rejected_files = HashSet::new();
+
+    let entries = match std::fs::read_dir(mods_path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Failed to read the mods directory at '{}' while checking manifests. Reason: {:?}", mods_path, e);
+            return (rejected_roots, rejected_files);
+        },
+    };
+
+    let is_strict = config::strict_manifests();
+
+    for entry in entries.filter_map(Result::ok) {
+        let root = entry.path();
+
+        if !root.is_dir() || !filter(&root) {
+            continue;
+        }
+
+        match manifest::verify_root(&root) {
+            Some(mismatches) if !mismatches.is_empty() && is_strict => {
+                warn!(
+                    "Mod root '{}' has {} file(s) that do not match its manifest and will be skipped entirely.",
+                    root.display(),
+                    mismatches.len()
+                );
+                rejected_roots.insert(root);
+            },
+            Some(mismatches) => rejected_files.extend(mismatches),
+            None => {},
+        }
+    }
+
+    (rejected_roots, rejected_files)
+}
+
+/// Checks if a file or directory is ignored because of its name, which by default is the case for the ones starting with a period.
+/// Skipping these is expected, so nothing gets logged about them.
+pub fn is_ignored_entry(name: &str) -> bool {
+    let (prefixes, suffixes) = &*IGNORED_ENTRIES;
+
+    prefixes.iter().any(|prefix| name.starts_with(prefix.as_str())) || suffixes.iter().any(|suffix| name.ends_with(suffix.as_str()))
+}
+
+/// Warns about mods that share a name, such as a directory and an archive with the same name next to it.
+/// Both are still loaded, but they can't be told apart when enabling, disabling or diagnosing conflicts by name.
+/// Files sitting directly in the mods directory are not mods either, which is also reported here unless their name is ignored.
+fn check_mods_directory(mods_path: &Utf8Path) {
+    let entries = match std::fs::read_dir(mods_path) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    let mut seen: HashMap<String, PathBuf> = HashMap::new();
+
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+
+        let name = match classify_top_level_entry(&path, is_ignored_entry) {
+            TopLevelEntry::Mod(name) => name,
+            TopLevelEntry::Loose => {
+                info!("File '{}' is ignored, files must be inside of a mod folder.", path.display());
+                continue;
+            },
+            TopLevelEntry::Ignored | TopLevelEntry::Invalid => continue,
+        };
+
+        if let Some(other) = seen.get(&name) {
+            warn!(
+                "Mods at '{}' and '{}' share the same name, which makes them ambiguous when referred to by name.",
+                other.display(),
+                path.display()
             );
+        } else {
+            seen.insert(name, path);
+        }
+    }
+}
+
+/// What an entry sitting directly in the mods directory is to the discovery
+#[derive(Debug, PartialEq, Eq)]
+enum TopLevelEntry {
+    /// A mod directory or archive, with its lowercase name
+    Mod(String),
+    /// A file that is not inside of a mod folder
+    Loose,
+    /// An entry ignored because of its name, which is expected and never reported
+    Ignored,
+    /// An entry with a name that is not valid UTF-8
+    Invalid,
+}
+
+fn classify_top_level_entry<I: Fn(&str) -> bool>(path: &Path, is_ignored: I) -> TopLevelEntry {
+    let file_name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => return TopLevelEntry::Invalid,
+    };
+
+    if is_ignored(file_name) {
+        return TopLevelEntry::Ignored;
+    }
+
+    let name = if path.is_dir() {
+        file_name
+    } else if path.has_extension("zip") {
+        match path.file_stem().and_then(|name| name.to_str()) {
+            Some(name) => name,
+            None => return TopLevelEntry::Invalid,
+        }
+    } else {
+        return TopLevelEntry::Loose;
+    };
+
+    // The SD card is not case sensitive, so neither are the names of the mods
+    TopLevelEntry::Mod(name.to_lowercase())
+}
+
+/// Lists the files of every mod distributed as a .zip archive at the top of the mods directory.
+/// Only the central directory of the archives is read here, the files are decompressed when the game requests them.
+fn discover_zip_mods<F, I, C>(mods_path: &Utf8Path, filter: F, ignore: I, collect: C) -> Vec<ZipEntry>
+where
+    F: Fn(&Path) -> bool,
+    I: Fn(&Path) -> bool,
+    C: Fn(&Path) -> bool,
+{
+    let entries = match std::fs::read_dir(mods_path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Failed to read the mods directory at '{}' while looking for archives. Reason: {:?}", mods_path, e);
+            return Vec::new();
         },
+    };
+
+    let is_strict = config::strict_manifests();
+    let allow_empty_files = config::allow_empty_files();
+
+    let mut zip_entries = Vec::new();
+
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+
+        if !path.is_file() || !path.has_extension("zip") || !filter(&path) {
+            continue;
+        }
+
+        let mut archive = match File::open(&path).map_err(ZipError::from).and_then(|file| ZipArchive::new(BufReader::new(file))) {
+            Ok(archive) => archive,
+            Err(e) => {
+                warn!("Failed to open archive '{}', it will be skipped. Reason: {:?}", path.display(), e);
+                continue;
+            },
+        };
+
+        let mut files = Vec::new();
+
+        for index in 0..archive.len() {
+            // Raw access only reads the entry header, there is no need to decompress anything yet
+            let file = match archive.by_index_raw(index) {
+                Ok(file) => file,
+                Err(e) => {
+                    warn!("Failed to read entry #{} of archive '{}', skipping it. Reason: {:?}", index, path.display(), e);
+                    continue;
+                },
+            };
+
+            if file.is_dir() {
+                continue;
+            }
+
+            let local = match archive_local_path(file.name()) {
+                Some(local) => local,
+                None => {
+                    warn!("Entry '{}' of archive '{}' does not have a valid path and will be skipped.", file.name(), path.display());
+                    continue;
+                },
+            };
+
+            files.push((index, local, file.size() as usize, file.crc32()));
+        }
+
+        if let Some(wrapper) = archive_wrapper_dir(&path, files.iter().map(|(_, local, ..)| local.as_path())) {
+            info!("Archive '{}' keeps its files in '{}', which is used as the root of the mod.", path.display(), wrapper.display());
+
+            for (_, local, ..) in files.iter_mut() {
+                *local = local.strip_prefix(&wrapper).map(Path::to_path_buf).unwrap_or_default();
+            }
+        }
+
+        // Archives go through the same checks as the directories of the mods, with their info.toml and manifest.json read from the archive
+        let info = match find_archive_entry(&files, "info.toml").map(|index| read_archive_entry(&mut archive, index)) {
+            Some(Ok(data)) => ModInfo::from_archive(&path, Some(&String::from_utf8_lossy(&data))),
+            Some(Err(e)) => {
+                warn!("Failed to read the mod info of archive '{}', it will be ignored. Reason: {:?}", path.display(), e);
+                ModInfo::from_archive(&path, None)
+            },
+            None => ModInfo::from_archive(&path, None),
+        };
+
+        if info.is_disabled() {
+            info!("Mod root '{}' is disabled by its info.toml and will be skipped.", path.display());
+            continue;
+        }
+
+        let rejected: HashSet<PathBuf> = match find_archive_entry(&files, "manifest.json").map(|index| read_archive_entry(&mut archive, index)) {
+            Some(Ok(data)) => {
+                let properties = files.iter().map(|(_, local, size, crc)| (local.clone(), (*size as u64, *crc))).collect();
+
+                match manifest::verify_archive(&path, &data, &properties) {
+                    Some(mismatches) if !mismatches.is_empty() && is_strict => {
+                        warn!(
+                            "Mod root '{}' has {} file(s) that do not match its manifest and will be skipped entirely.",
+                            path.display(),
+                            mismatches.len()
+                        );
+                        continue;
+                    },
+                    Some(mismatches) => mismatches.into_iter().collect(),
+                    None => HashSet::new(),
+                }
+            },
+            Some(Err(e)) => {
+                warn!("Failed to read the manifest of archive '{}', it will not be checked. Reason: {:?}", path.display(), e);
+                HashSet::new()
+            },
+            None => HashSet::new(),
+        };
+
+        let reader = Arc::new(Mutex::new(archive));
+
+        for (index, local, size, _) in files {
+            if ignore(&local) || rejected.contains(&local) {
+                continue;
+            }
+
+            // An empty replacement is almost always the result of an interrupted copy, which the game would choke on
+            if size == 0 && !allow_empty_files {
+                warn!("File '{}' in archive '{}' is empty, likely an incomplete copy. It will be skipped.", local.display(), path.display());
+                continue;
+            }
+
+            // Configs, plugins, patch files and streams are all read straight from the SD card
+            if collect(&local) || local.has_extension("zst") || local.is_stream() {
+                warn!(
+                    "File '{}' in archive '{}' is not supported inside of archives and will be skipped. Extract the mod to use it.",
+                    local.display(),
+                    path.display()
+                );
+                continue;
+            }
+
+            zip_entries.push(ZipEntry {
+                archive: path.clone(),
+                reader: reader.clone(),
+                index,
+                local,
+                size,
+            });
+        }
     }
 
-    load_and_run_plugins(launchpad.collected_paths());
+    zip_entries
+}
 
-    launchpad
+/// Finds the index of the entry of an archive with the provided path relative to the root of the mod
+fn find_archive_entry(files: &[(usize, PathBuf, usize, u32)], local: &str) -> Option<usize> {
+    files.iter().find(|(_, path, ..)| path == Path::new(local)).map(|(index, ..)| *index)
+}
+
+/// Decompresses a whole entry of an archive, which is only meant for the small files describing a mod
+fn read_archive_entry<R: Read + Seek>(archive: &mut ZipArchive<R>, index: usize) -> Result<Vec<u8>, ZipError> {
+    let mut file = archive.by_index(index)?;
+    let mut data = Vec::with_capacity(file.size() as usize);
+    file.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Gets the path an entry of an archive replaces from its name. Archives made on Windows can use backslashes,
+/// and names that would point outside of the archive are rejected.
+fn archive_local_path(name: &str) -> Option<PathBuf> {
+    let local = PathBuf::from(name.replace('\\', "/"));
+
+    if local.as_os_str().is_empty() || !local.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir)) {
+        None
+    } else {
+        Some(local)
+    }
+}
+
+/// Archives made by zipping the directory of a mod keep that directory at their root. It is recognized when every file is inside of it
+/// and it is either named after the archive or holds the info.toml of the mod, so that its name can be stripped from the paths.
+fn archive_wrapper_dir<'a>(archive: &Path, locals: impl IntoIterator<Item = &'a Path>) -> Option<PathBuf> {
+    let locals: Vec<&Path> = locals.into_iter().collect();
+    let mut wrapper = None;
+
+    for local in locals.iter() {
+        let mut components = local.components();
+        let first = components.next()?;
+
+        // A file at the root of the archive means that the archive is the root of the mod
+        if components.next().is_none() {
+            return None;
+        }
+
+        match wrapper {
+            Some(wrapper) if wrapper != first => return None,
+            _ => wrapper = Some(first),
+        }
+    }
+
+    let wrapper = Path::new(wrapper?.as_os_str());
+
+    let is_named_after_archive = archive
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .zip(wrapper.to_str())
+        .map_or(false, |(stem, wrapper)| stem.eq_ignore_ascii_case(wrapper));
+
+    if is_named_after_archive || locals.contains(&wrapper.join("info.toml").as_path()) {
+        Some(wrapper.to_path_buf())
+    } else {
+        None
+    }
+}
+
+/// A file that is provided by more than one mod, as written in the conflict report
+#[derive(Debug, Serialize)]
+struct ConflictReportEntry {
+    path: PathBuf,
+    hash: String,
+    roots: Vec<PathBuf>,
+    /// The info of every root, in the same order, so that users can tell which mod a root belongs to
+    mods: Vec<ConflictReportMod>,
+}
+
+#[derive(Debug, Serialize)]
+struct ConflictReportMod {
+    root: PathBuf,
+    #[serde(flatten)]
+    info: ModInfo,
+}
+
+/// Groups the conflicts by the file they are about. Files that end up with the same hash are grouped together,
+/// since the game can only load one of them.
+fn build_conflict_report(conflicts: Vec<ConflictKind>, mod_infos: &HashMap<PathBuf, ModInfo>) -> Vec<ConflictReportEntry> {
+    let mut entries: HashMap<Hash40, ConflictReportEntry> = HashMap::new();
+
+    for conflict in conflicts.into_iter() {
+        if let ConflictKind::StandardConflict {
+            error_root,
+            local,
+            source_root,
+        } = conflict
+        {
+            let hash = local.smash_hash().unwrap_or_else(|_| Hash40::from(local.to_string_lossy().as_ref()));
+
+            let entry = entries.entry(hash).or_insert_with(|| ConflictReportEntry {
+                path: local,
+                hash: format!("{:#x}", hash.0),
+                roots: Vec::new(),
+                mods: Vec::new(),
+            });
+
+            for root in [source_root, error_root] {
+                if !entry.roots.contains(&root) {
+                    // Archives have no info of their own, so they are described by their name only
+                    let info = mod_infos.get(&root).cloned().unwrap_or_else(|| ModInfo::from_root(&root));
+                    entry.mods.push(ConflictReportMod { root: root.clone(), info });
+                    entry.roots.push(root);
+                }
+            }
+        }
+    }
+
+    let mut report: Vec<ConflictReportEntry> = entries.into_values().collect();
+    report.sort_by(|a, b| a.path.cmp(&b.path));
+    report
+}
+
+/// Writes the conflict report to the provided path as a JSON array
+fn write_conflict_report(report: &[ConflictReportEntry], path: &Utf8Path) -> std::io::Result<()> {
+    let json = serde_json::to_string_pretty(report)?;
+    std::fs::write(path, json.as_bytes())
+}
+
+/// Compiles the discovery exclusion patterns into a single set, skipping the ones that aren't valid globs
+fn build_exclusions(patterns: &[String]) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+
+    for pattern in patterns {
+        match Glob::new(pattern) {
+            Ok(glob) => {
+                builder.add(glob);
+            },
+            Err(err) => warn!("Discovery exclusion pattern '{}' is invalid and will be ignored. Reason: {}", pattern, err),
+        }
+    }
+
+    builder.build().unwrap_or_else(|err| {
+        warn!("Failed to build the discovery exclusion patterns, no file will be excluded. Reason: {}", err);
+        GlobSet::empty()
+    })
 }
 
 fn mount_prebuilt_nrr<A: FileLoader>(tree: &Tree<A>) -> Result<Option<RegistrationInfo>, NrrRegistrationFailedError>
@@ -353,3 +1023,71 @@ pub fn load_and_run_plugins(plugins: &[(PathBuf, PathBuf)]) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archive_paths_with_backslashes_hash_like_forward_slashes() {
+        let local = archive_local_path("fighter\\mario\\model\\body\\c00\\model.numdlb").unwrap();
+
+        assert_eq!(local, PathBuf::from("fighter/mario/model/body/c00/model.numdlb"));
+        assert_eq!(
+            local.smash_hash().unwrap(),
+            Path::new("fighter/mario/model/body/c00/model.numdlb").smash_hash().unwrap()
+        );
+    }
+
+    #[test]
+    fn archive_paths_outside_of_the_archive_are_rejected() {
+        assert_eq!(archive_local_path("..\\fighter\\mario\\model.numdlb"), None);
+        assert_eq!(archive_local_path("/fighter/mario/model.numdlb"), None);
+        assert_eq!(archive_local_path(""), None);
+    }
+
+    #[test]
+    fn mod_roots_are_hashed_by_their_absolute_path() {
+        let mods_path = Utf8PathBuf::from_path_buf(std::env::temp_dir().join(format!("arcropolis-mod-roots-{}", std::process::id()))).unwrap();
+
+        std::fs::create_dir_all(mods_path.join("Mario Skin/fighter/mario")).unwrap();
+        std::fs::create_dir_all(mods_path.join("UI Mod")).unwrap();
+        std::fs::write(mods_path.join("Mario Skin/fighter/mario/model.numdlb"), b"").unwrap();
+        std::fs::write(mods_path.join("Zipped Mod.zip"), b"").unwrap();
+        std::fs::write(mods_path.join("readme.txt"), b"").unwrap();
+
+        let roots = collect_mod_roots(&mods_path);
+        std::fs::remove_dir_all(&mods_path).unwrap();
+
+        let expected: HashSet<Hash40> = ["Mario Skin", "UI Mod", "Zipped Mod.zip"]
+            .iter()
+            .map(|name| Hash40::from(mods_path.join(name).as_str()))
+            .collect();
+
+        assert!(roots == expected);
+    }
+
+    #[test]
+    fn ignored_top_level_entries_are_not_reported() {
+        let mods_path = std::env::temp_dir().join(format!("arcropolis-top-level-{}", std::process::id()));
+
+        std::fs::create_dir_all(mods_path.join("Mario Skin")).unwrap();
+        std::fs::create_dir_all(mods_path.join(".git")).unwrap();
+        std::fs::write(mods_path.join(".DS_Store"), b"").unwrap();
+        std::fs::write(mods_path.join("._Zipped Mod.zip"), b"").unwrap();
+        std::fs::write(mods_path.join("Zipped Mod.zip"), b"").unwrap();
+        std::fs::write(mods_path.join("readme.txt"), b"").unwrap();
+
+        let is_ignored = |name: &str| name.starts_with('.');
+        let classify = |name: &str| classify_top_level_entry(&mods_path.join(name), is_ignored);
+
+        assert_eq!(classify("Mario Skin"), TopLevelEntry::Mod(String::from("mario skin")));
+        assert_eq!(classify("Zipped Mod.zip"), TopLevelEntry::Mod(String::from("zipped mod")));
+        assert_eq!(classify("readme.txt"), TopLevelEntry::Loose);
+        assert_eq!(classify(".git"), TopLevelEntry::Ignored);
+        assert_eq!(classify(".DS_Store"), TopLevelEntry::Ignored);
+        assert_eq!(classify("._Zipped Mod.zip"), TopLevelEntry::Ignored);
+
+        std::fs::remove_dir_all(&mods_path).unwrap();
+    }
+}
@@ -0,0 +1,65 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// Metadata of a mod, read from the `info.toml` at its root.
+/// Uses the same keys as the entries of the mod manager, so that a single file serves both, and adds a few that are used during discovery.
+/// Everything is also written to the conflict report, next to the roots it describes.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ModInfo {
+    pub display_name: Option<String>,
+    pub authors: Option<String>,
+    pub version: Option<String>,
+    pub description: Option<String>,
+    pub category: Option<String>,
+    /// Meant to decide which mod wins a conflict. It is recorded, but discovery does not take it into account yet.
+    pub priority: Option<i32>,
+    /// Keeps the mod from being loaded, no matter the active preset
+    pub is_disabled: Option<bool>,
+}
+
+impl ModInfo {
+    /// Reads the info of a mod root. A missing or malformed file never fails, and the name of the directory is used when no name is provided.
+    pub fn from_root(root: &Path) -> Self {
+        let info_path = root.join("info.toml");
+
+        let info = if info_path.exists() {
+            match std::fs::read_to_string(&info_path) {
+                Ok(data) => Self::parse(&data, &info_path),
+                Err(e) => {
+                    warn!("Failed to read mod info '{}', it will be ignored. Reason: {:?}", info_path.display(), e);
+                    ModInfo::default()
+                },
+            }
+        } else {
+            ModInfo::default()
+        };
+
+        info.named_after(root)
+    }
+
+    /// Same as `from_root` for a mod distributed as an archive, with the contents of its info.toml if it has one
+    pub fn from_archive(archive: &Path, data: Option<&str>) -> Self {
+        data.map_or_else(ModInfo::default, |data| Self::parse(data, &archive.join("info.toml"))).named_after(archive)
+    }
+
+    fn parse(data: &str, info_path: &Path) -> Self {
+        toml::from_str(data).unwrap_or_else(|e| {
+            warn!("Mod info '{}' is malformed and will be ignored. Reason: {}", info_path.display(), e);
+            ModInfo::default()
+        })
+    }
+
+    fn named_after(mut self, root: &Path) -> Self {
+        if self.display_name.is_none() {
+            self.display_name = root.file_name().map(|name| name.to_string_lossy().into_owned());
+        }
+
+        self
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.is_disabled.unwrap_or(false)
+    }
+}
@@ -1,7 +1,8 @@
 use std::{
     collections::VecDeque,
     fs::{self, File},
-    io::{Cursor, Read},
+    io::{BufReader, Cursor, Read},
+    sync::{Arc, Mutex},
 };
 
 use base64::{engine::general_purpose::STANDARD as BASE64_STANDARD, Engine};
@@ -61,10 +62,16 @@ pub enum ApiLoaderError {
     #[error("IO Error")]
     IO(#[from] std::io::Error),
 
+    #[error("Failed to read a file from a zip archive.")]
+    Zip(#[from] zip::result::ZipError),
+
     #[error("{0}")]
     Other(String),
 }
 
+/// Largest buffer provided to a chunk callback in a single call
+const CHUNK_CALLBACK_SIZE: usize = 0x10000;
+
 #[derive(Debug, Clone, Copy)]
 enum ApiLoadType {
     Nus3bankPatch,
@@ -73,9 +80,14 @@ enum ApiLoadType {
     Nus3audioPatch,
     MotionlistPatch,
     BgmPropertyPatch,
+    JsonPatch,
+    Randomizer,
     Generic,
+    Chunk,
     Stream,
     Extension,
+    Zip,
+    ManualOverride,
 }
 
 impl ApiLoadType {
@@ -92,19 +104,32 @@ impl ApiLoadType {
             Ok(ApiLoadType::MotionlistPatch)
         } else if root.ends_with("patch-bgm_property") {
             Ok(ApiLoadType::BgmPropertyPatch)
+        } else if root.ends_with("patch-json") {
+            Ok(ApiLoadType::JsonPatch)
         } else if root.ends_with("generic-cb") {
             Ok(ApiLoadType::Generic)
+        } else if root.ends_with("chunk-cb") {
+            Ok(ApiLoadType::Chunk)
         } else if root.ends_with("stream-cb") {
             Ok(ApiLoadType::Stream)
         } else if root.ends_with("extension-cb") {
             Ok(ApiLoadType::Extension)
+        } else if root.ends_with("zip") {
+            Ok(ApiLoadType::Zip)
+        } else if root.ends_with("randomizer") {
+            Ok(ApiLoadType::Randomizer)
+        } else if root.ends_with("manual-override") {
+            Ok(ApiLoadType::ManualOverride)
         } else {
             Err(ApiLoaderError::Other(format!("Cannot find ApiLoadType for root {}", root.display())))
         }
     }
 
     pub fn path_exists(self, _local: &Path) -> bool {
-        matches!(self, ApiLoadType::Nus3bankPatch)
+        matches!(
+            self,
+            ApiLoadType::Nus3bankPatch | ApiLoadType::Zip | ApiLoadType::Randomizer | ApiLoadType::ManualOverride
+        )
     }
 
     pub fn get_file_size(self, local: &Path) -> Option<usize> {
@@ -116,6 +141,17 @@ impl ApiLoadType {
                     .and_then(|hash| arc.get_file_data_from_hash(hash, config::region()).ok())
                     .map(|x| x.decomp_size as usize)
             },
+            ApiLoadType::Zip => local
+                .smash_hash()
+                .ok()
+                .and_then(ApiLoader::get_zip_entry_for_hash)
+                .map(|entry| entry.size),
+            ApiLoadType::ManualOverride => local
+                .smash_hash()
+                .ok()
+                .and_then(ApiLoader::get_manual_override_for_hash)
+                .and_then(|path| fs::metadata(path).ok())
+                .map(|metadata| metadata.len() as usize),
             _ => None,
         }
     }
@@ -131,6 +167,11 @@ impl ApiLoadType {
                     Ok(FileEntryType::File)
                 }
             },
+            // Directories are not recorded when reading an archive, so every entry is a file
+            ApiLoadType::Zip => Ok(FileEntryType::File),
+            // The directory is served as the file it is named after
+            ApiLoadType::Randomizer => Ok(FileEntryType::File),
+            ApiLoadType::ManualOverride => Ok(FileEntryType::File),
             _ => Err(ApiLoaderError::Other("Unimplemented ApiLoadType!".to_string())),
         }
     }
@@ -420,6 +461,29 @@ impl ApiLoadType {
                 let data = writer.into_inner();
                 Ok((data.len(), data))
             },
+            ApiLoadType::JsonPatch => {
+                let patches = if let Some(patches) = ApiLoader::get_json_patches_for_hash(local.smash_hash()?) {
+                    patches
+                } else {
+                    return Err(ApiLoaderError::Other("No patches found for file of type JSON!".to_string()));
+                };
+
+                let data = ApiLoader::handle_load_base_file(local)?;
+                let mut json: serde_json::Value =
+                    serde_json::from_slice(&data).map_err(|_| ApiLoaderError::Other("Unable to parse JSON data!".to_string()))?;
+
+                let append_arrays = config::json_patch_append_arrays();
+
+                for patch_path in patches.iter() {
+                    match serde_json::from_slice(&fs::read(patch_path)?) {
+                        Ok(patch) => merge_json(&mut json, patch, append_arrays),
+                        Err(err) => warn!("JSON patch `{}` is malformed, skipping. Reason: {}", patch_path.display(), err),
+                    }
+                }
+
+                let data = serde_json::to_vec(&json).map_err(|_| ApiLoaderError::Other("Unable to write patched JSON data!".to_string()))?;
+                Ok((data.len(), data))
+            },
             ApiLoadType::Generic if let ApiCallback::GenericCallback(cb) = usr_fn => {
                 let hash = local.smash_hash()?;
                 let mut size = 0;
@@ -443,6 +507,38 @@ impl ApiLoadType {
                 Ok((size, vec))
             },
             ApiLoadType::Generic => Err(ApiLoaderError::InvalidCb),
+            ApiLoadType::Chunk if let ApiCallback::ChunkCallback(cb) = usr_fn => {
+                let hash = local.smash_hash()?;
+                let mut size = 0;
+
+                if !crate::api::file::arcrop_get_decompressed_size(hash, &mut size) {
+                    return Err(ApiLoaderError::Other("Unable to create buffer!".to_string()));
+                }
+
+                let mut vec = vec![0; size];
+                let mut offset = 0;
+
+                // Callbacks never get more than a chunk at once, so they don't need the entire file ready
+                while offset < size {
+                    let length = (size - offset).min(CHUNK_CALLBACK_SIZE);
+                    let written = cb(hash.0, offset, vec[offset..].as_mut_ptr(), length);
+
+                    if written == 0 {
+                        break;
+                    }
+
+                    offset += written.min(length);
+                }
+
+                if offset == 0 {
+                    return Err(ApiLoaderError::Other("Callback did not load file!".to_string()));
+                }
+
+                vec.truncate(offset);
+
+                Ok((size, vec))
+            },
+            ApiLoadType::Chunk => Err(ApiLoaderError::InvalidCb),
             ApiLoadType::Stream if let ApiCallback::StreamCallback(cb) = usr_fn => {
                 let hash = local.smash_hash()?;
                 let mut vec = Vec::with_capacity(0x100);
@@ -459,15 +555,98 @@ impl ApiLoadType {
                 Ok((file_size, vec))
             },
             ApiLoadType::Stream => Err(ApiLoaderError::InvalidCb),
+            ApiLoadType::Zip => {
+                let entry = if let Some(entry) = ApiLoader::get_zip_entry_for_hash(local.smash_hash()?) {
+                    entry
+                } else {
+                    return Err(ApiLoaderError::Other("No zip entry found for file!".to_string()));
+                };
+
+                let data = entry.read()?;
+                Ok((data.len(), data))
+            },
+            ApiLoadType::Randomizer => {
+                let entry = if let Some(entry) = ApiLoader::get_randomizer_for_hash(local.smash_hash()?) {
+                    entry
+                } else {
+                    return Err(ApiLoaderError::Other("No randomizer found for file!".to_string()));
+                };
+
+                let data = fs::read(entry.pick())?;
+                Ok((data.len(), data))
+            },
+            ApiLoadType::ManualOverride => {
+                let path = if let Some(path) = ApiLoader::get_manual_override_for_hash(local.smash_hash()?) {
+                    path
+                } else {
+                    return Err(ApiLoaderError::Other("No manual override found for file!".to_string()));
+                };
+
+                let data = fs::read(path)?;
+                Ok((data.len(), data))
+            },
             _ => Err(ApiLoaderError::Other("Unimplemented ApiLoadType!".to_string()))
         }
     }
 }
 
+/// Merges a JSON patch over the base data, in the order the patches were discovered.
+/// Objects are merged key by key, arrays are appended to or replaced depending on the configuration, and any other value from the patch replaces the base one.
+fn merge_json(base: &mut serde_json::Value, patch: serde_json::Value, append_arrays: bool) {
+    use serde_json::Value;
+
+    match (base, patch) {
+        (Value::Object(base), Value::Object(patch)) => {
+            for (key, value) in patch {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge_json(base_value, value, append_arrays),
+                    None => {
+                        base.insert(key, value);
+                    },
+                }
+            }
+        },
+        (Value::Array(base), Value::Array(mut patch)) if append_arrays => base.append(&mut patch),
+        (base, patch) => *base = patch,
+    }
+}
+
+/// An archive opened during discovery. Parsing the central directory again on every load is slow, so it is shared by all of its entries.
+pub type SharedArchive = Arc<Mutex<zip::ZipArchive<BufReader<File>>>>;
+
+/// A file provided by a mod that is distributed as a single .zip archive
+#[derive(Debug, Clone)]
+pub struct ZipEntry {
+    /// Path to the archive on the SD card
+    pub archive: PathBuf,
+    /// The opened archive, shared with the other entries of the same archive
+    pub reader: SharedArchive,
+    /// Index of the entry inside of the archive
+    pub index: usize,
+    /// Path of the file relative to the root of the mod, which is the path it replaces in the data.arc
+    pub local: PathBuf,
+    /// Decompressed size of the entry
+    pub size: usize,
+}
+
+impl ZipEntry {
+    /// Reads the decompressed contents of the entry back out of its archive
+    pub fn read(&self) -> Result<Vec<u8>, ApiLoaderError> {
+        let mut archive = self.reader.lock().unwrap();
+        let mut file = archive.by_index(self.index)?;
+
+        let mut data = Vec::with_capacity(self.size);
+        file.read_to_end(&mut data)?;
+
+        Ok(data)
+    }
+}
+
 #[derive(Copy, Clone)]
 pub enum ApiCallback {
     None,
     GenericCallback(arcropolis_api::CallbackFn),
+    ChunkCallback(crate::api::ChunkCallbackFn),
     StreamCallback(arcropolis_api::StreamCallbackFn),
 }
 
@@ -491,6 +670,11 @@ pub struct ApiLoader {
     nus3audio_patches: HashMap<Hash40, Vec<PathBuf>>,
     motionlist_patches: HashMap<Hash40, Vec<PathBuf>>,
     bgm_property_patches: HashMap<Hash40, Vec<PathBuf>>,
+    json_patches: HashMap<Hash40, Vec<PathBuf>>,
+    randomizers: HashMap<Hash40, RandomizerEntry>,
+    zip_entries: HashMap<Hash40, ZipEntry>,
+    // Every override of a file, the most recent one being used
+    manual_overrides: HashMap<Hash40, Vec<PathBuf>>,
 }
 
 unsafe impl Send for ApiLoader {}
@@ -514,6 +698,19 @@ impl ApiLoader {
         }
     }
 
+    /// Checks if the file is provided by the API tree rather than by a mod
+    pub fn is_virtual_file(&self, hash: Hash40) -> bool {
+        self.function_map.contains_key(&hash)
+    }
+
+    // Gets the root of the most recent entry of a file, which is the first one tried when loading it
+    fn current_root(&self, hash: Hash40) -> Option<&Path> {
+        self.function_map
+            .get(&hash)
+            .and_then(|entry| unsafe { (*entry.get()).functions.front() })
+            .map(|(root, _)| root.as_path())
+    }
+
     fn use_virtual_file(&self, local: &Path) -> Option<(&Path, ApiCallback)> {
         local.smash_hash().ok().and_then(|x| self.function_map.get(&x)).and_then(|entry| {
             let data = entry.get();
@@ -592,6 +789,59 @@ impl ApiLoader {
         cached.virt().loader.bgm_property_patches.get(&hash)
     }
 
+    pub fn get_json_patches_for_hash(hash: Hash40) -> Option<&'static Vec<PathBuf>> {
+        let filesystem = unsafe { &*crate::GLOBAL_FILESYSTEM.get_mut().unwrap() };
+        let cached = filesystem.get();
+
+        cached.virt().loader.json_patches.get(&hash)
+    }
+
+    pub fn get_randomizer_for_hash(hash: Hash40) -> Option<&'static RandomizerEntry> {
+        let filesystem = unsafe { &*crate::GLOBAL_FILESYSTEM.get_mut().unwrap() };
+        let cached = filesystem.get();
+
+        cached.virt().loader.randomizers.get(&hash)
+    }
+
+    pub fn get_zip_entry_for_hash(hash: Hash40) -> Option<&'static ZipEntry> {
+        let filesystem = unsafe { &*crate::GLOBAL_FILESYSTEM.get_mut().unwrap() };
+        let cached = filesystem.get();
+
+        cached.virt().loader.zip_entries.get(&hash)
+    }
+
+    pub fn get_manual_override_for_hash(hash: Hash40) -> Option<&'static PathBuf> {
+        let filesystem = unsafe { &*crate::GLOBAL_FILESYSTEM.get_mut().unwrap() };
+        let cached = filesystem.get();
+
+        cached.virt().loader.manual_override(hash)
+    }
+
+    /// Gets the path of the manual override that is currently used for a file
+    pub fn manual_override(&self, hash: Hash40) -> Option<&PathBuf> {
+        self.manual_overrides.get(&hash).and_then(|paths| paths.last())
+    }
+
+    /// Gets the path of the manual override a file is loaded from, which is only the case while no more recent entry provides it
+    pub fn current_manual_override(&self, hash: Hash40) -> Option<&PathBuf> {
+        match self.current_root(hash).map(ApiLoadType::from_root) {
+            Some(Ok(ApiLoadType::ManualOverride)) => self.manual_override(hash),
+            _ => None,
+        }
+    }
+
+    /// Gets the file a randomizer picked the last time it was loaded, if it is the most recent entry of the file
+    pub fn current_randomizer_pick(&self, hash: Hash40) -> Option<&Path> {
+        match self.current_root(hash).map(ApiLoadType::from_root) {
+            Some(Ok(ApiLoadType::Randomizer)) => self.randomizers.get(&hash).and_then(RandomizerEntry::last_pick),
+            _ => None,
+        }
+    }
+
+    pub fn zip_entries(&self) -> impl Iterator<Item = &ZipEntry> {
+        self.zip_entries.values()
+    }
+
     pub fn insert_prc_patch(&mut self, hash: Hash40, path: &Path) {
         if let Some(list) = self.param_patches.get_mut(&hash) {
             list.push(path.to_path_buf())
@@ -632,6 +882,73 @@ impl ApiLoader {
         }
     }
 
+    pub fn insert_json_patch(&mut self, hash: Hash40, path: &Path) {
+        if let Some(list) = self.json_patches.get_mut(&hash) {
+            list.push(path.to_path_buf())
+        } else {
+            self.json_patches.insert(hash, vec![path.to_path_buf()]);
+        }
+    }
+
+    pub fn insert_randomizer(&mut self, hash: Hash40, entry: RandomizerEntry) {
+        self.randomizers.insert(hash, entry);
+    }
+
+    pub fn insert_zip_entry(&mut self, hash: Hash40, entry: ZipEntry) {
+        self.zip_entries.insert(hash, entry);
+    }
+
+    pub fn insert_manual_override(&mut self, hash: Hash40, path: PathBuf) {
+        self.manual_overrides.entry(hash).or_default().push(path);
+    }
+
+    /// Mounts a manual override of a file. The base is the file on the SD card that a mod provided for it until now, if any.
+    /// It is kept as an override of its own underneath, so that the file is served from it again once the override is removed.
+    pub fn mount_manual_override(&mut self, hash: Hash40, root: &Path, path: PathBuf, base: Option<PathBuf>) {
+        if let Some(base) = base {
+            self.push_entry(hash, root, ApiCallback::None);
+            self.insert_manual_override(hash, base);
+        }
+
+        self.push_entry(hash, root, ApiCallback::None);
+        self.insert_manual_override(hash, path);
+    }
+
+    /// Removes an override mounted through `mount_manual_override`, after which the file is served by whatever provided it before
+    pub fn unmount_manual_override(&mut self, hash: Hash40, root: &Path, path: &Path) {
+        self.remove_manual_override(hash, path);
+        self.remove_entry(hash, root);
+    }
+
+    /// Removes the most recent override of a file that points to the provided path
+    pub fn remove_manual_override(&mut self, hash: Hash40, path: &Path) {
+        if let Some(paths) = self.manual_overrides.get_mut(&hash) {
+            if let Some(index) = paths.iter().rposition(|entry| entry == path) {
+                paths.remove(index);
+            }
+
+            if paths.is_empty() {
+                self.manual_overrides.remove(&hash);
+            }
+        }
+    }
+
+    /// Removes the most recent entry of a file that was pushed for the provided root
+    pub fn remove_entry(&mut self, hash: Hash40, root: &Path) {
+        if let Some(entry) = self.function_map.get_mut(&hash) {
+            let functions = &mut entry.get_mut().functions;
+
+            // Entries are pushed to the front, so the first one found from the front is the most recent
+            if let Some(index) = functions.iter().position(|(entry_root, _)| entry_root == root) {
+                functions.remove(index);
+            }
+
+            if functions.is_empty() {
+                self.function_map.remove(&hash);
+            }
+        }
+    }
+
     fn get_stream_cb_path(&self, local: &Path) -> Option<String> {
         if let Some((root_path, callback)) = self.use_virtual_file(local) {
             let result = match ApiLoadType::from_root(root_path) {
@@ -719,8 +1036,23 @@ impl FileLoader for ApiLoader {
     }
 
     fn get_actual_path(&self, root_path: &Path, local_path: &Path) -> Option<PathBuf> {
+        // The tree keeps the root of the last entry inserted for a path, even once that entry has been removed.
+        // Loading goes through the most recent entry of the file instead, so the path has to be resolved from it as well.
+        let root_path = local_path.smash_hash().ok().and_then(|hash| self.current_root(hash)).unwrap_or(root_path);
+
         if root_path.ends_with("stream-cb") {
             Some(self.get_stream_cb_path(local_path).map_or(root_path.join(local_path), PathBuf::from))
+        } else if root_path.ends_with("randomizer") {
+            // Streams are read by the game from their actual path, so this is where the file gets picked for them
+            let entry = local_path.smash_hash().ok().and_then(|hash| self.randomizers.get(&hash));
+            Some(entry.map_or(root_path.join(local_path), |entry| entry.pick().to_path_buf()))
+        } else if root_path.ends_with("manual-override") {
+            let path = local_path
+                .smash_hash()
+                .ok()
+                .and_then(|hash| self.manual_overrides.get(&hash))
+                .and_then(|paths| paths.last());
+            Some(path.map_or(root_path.join(local_path), PathBuf::clone))
         } else {
             Some(root_path.join(local_path))
         }
@@ -784,3 +1116,74 @@ impl FileLoader for ArcLoader {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCAL: &str = "fighter/mario/model/body/c00/model.numdlb";
+
+    fn override_root() -> &'static Path {
+        Path::new("api:/manual-override")
+    }
+
+    fn local_hash() -> Hash40 {
+        Path::new(LOCAL).smash_hash().unwrap()
+    }
+
+    #[test]
+    fn removing_the_override_of_a_vanilla_file_unmounts_it() {
+        let mut loader = ApiLoader::default();
+        let path = PathBuf::from("sd:/overrides/model.numdlb");
+
+        loader.mount_manual_override(local_hash(), override_root(), path.clone(), None);
+        assert!(loader.is_virtual_file(local_hash()));
+        assert_eq!(loader.get_actual_path(override_root(), Path::new(LOCAL)), Some(path.clone()));
+
+        loader.unmount_manual_override(local_hash(), override_root(), &path);
+        assert!(!loader.is_virtual_file(local_hash()));
+        assert_eq!(loader.manual_override(local_hash()), None);
+    }
+
+    #[test]
+    fn removing_the_override_of_a_mod_file_serves_the_mod_file_again() {
+        let mut loader = ApiLoader::default();
+        let path = PathBuf::from("sd:/overrides/model.numdlb");
+        let base = PathBuf::from("sd:/ultimate/mods/Mario Skin/fighter/mario/model/body/c00/model.numdlb");
+
+        loader.mount_manual_override(local_hash(), override_root(), path.clone(), Some(base.clone()));
+        assert_eq!(loader.get_actual_path(override_root(), Path::new(LOCAL)), Some(path.clone()));
+
+        loader.unmount_manual_override(local_hash(), override_root(), &path);
+        assert_eq!(loader.manual_override(local_hash()), Some(&base));
+        assert_eq!(loader.get_actual_path(override_root(), Path::new(LOCAL)), Some(base));
+    }
+
+    #[test]
+    fn callbacks_registered_after_an_override_take_priority() {
+        let mut loader = ApiLoader::default();
+        let path = PathBuf::from("sd:/overrides/model.numdlb");
+
+        loader.mount_manual_override(local_hash(), override_root(), path.clone(), None);
+        assert_eq!(loader.current_manual_override(local_hash()), Some(&path));
+
+        loader.push_entry(local_hash(), Path::new("api:/generic-cb"), ApiCallback::None);
+        assert_eq!(loader.current_manual_override(local_hash()), None);
+    }
+
+    #[test]
+    fn removing_the_override_of_a_callback_serves_the_callback_again() {
+        let mut loader = ApiLoader::default();
+        let path = PathBuf::from("sd:/overrides/model.numdlb");
+        let callback_root = Path::new("api:/generic-cb");
+
+        loader.push_entry(local_hash(), callback_root, ApiCallback::None);
+        loader.mount_manual_override(local_hash(), override_root(), path.clone(), None);
+        loader.unmount_manual_override(local_hash(), override_root(), &path);
+
+        // The tree still has the root of the override for this path
+        assert!(loader.is_virtual_file(local_hash()));
+        assert_eq!(loader.current_manual_override(local_hash()), None);
+        assert_eq!(loader.get_actual_path(override_root(), Path::new(LOCAL)), Some(callback_root.join(LOCAL)));
+    }
+}
@@ -0,0 +1,168 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+/// Expected properties of a file listed in the manifest of a mod. Both are optional, so that authors can pick which ones are checked.
+#[derive(Debug, Deserialize)]
+struct ManifestEntry {
+    size: Option<u64>,
+    crc32: Option<u32>,
+}
+
+/// Checks the files of a mod root against the `manifest.json` at its top, which maps the path of files relative to the root to what they are expected to be.
+/// Returns None if the root does not have a manifest, otherwise returns the full path of every file that did not match.
+pub fn verify_root(root: &Path) -> Option<Vec<PathBuf>> {
+    let manifest_path = root.join("manifest.json");
+
+    if !manifest_path.exists() {
+        return None;
+    }
+
+    let manifest = match std::fs::read(&manifest_path) {
+        Ok(data) => parse(&data, &manifest_path)?,
+        Err(e) => {
+            warn!("Failed to read manifest '{}', it will not be checked. Reason: {:?}", manifest_path.display(), e);
+            return None;
+        },
+    };
+
+    let mut mismatches = Vec::new();
+
+    for (local, expected) in manifest.iter() {
+        let path = root.join(local.trim_start_matches('/'));
+
+        let size = match std::fs::metadata(&path) {
+            Ok(metadata) => metadata.len(),
+            Err(_) => {
+                warn!("File '{}' is listed in the manifest of '{}' but is missing.", local, root.display());
+                continue;
+            },
+        };
+
+        if let Some(expected_size) = expected.size {
+            if size != expected_size {
+                warn!(
+                    "File '{}' does not match the manifest of its mod and will be skipped! Expected size: {:#x}, actual size: {:#x}",
+                    path.display(),
+                    expected_size,
+                    size
+                );
+                mismatches.push(path);
+                continue;
+            }
+        }
+
+        if let Some(expected_crc) = expected.crc32 {
+            match compute_crc32(&path) {
+                Ok(crc) if crc == expected_crc => {},
+                Ok(crc) => {
+                    warn!(
+                        "File '{}' does not match the manifest of its mod and will be skipped! Expected CRC32: {:#010x}, actual CRC32: {:#010x}",
+                        path.display(),
+                        expected_crc,
+                        crc
+                    );
+                    mismatches.push(path);
+                },
+                Err(e) => {
+                    warn!("Failed to read '{}' to check it against the manifest, it will be skipped. Reason: {:?}", path.display(), e);
+                    mismatches.push(path);
+                },
+            }
+        }
+    }
+
+    Some(mismatches)
+}
+
+/// Same as `verify_root` for a mod distributed as an archive. The files are checked against the size and CRC32 recorded for them in the archive,
+/// keyed by their path relative to the root of the mod, so that nothing has to be decompressed. Returns the relative path of every file that did not match.
+pub fn verify_archive(archive: &Path, data: &[u8], files: &HashMap<PathBuf, (u64, u32)>) -> Option<Vec<PathBuf>> {
+    let manifest = parse(data, &archive.join("manifest.json"))?;
+
+    let mut mismatches = Vec::new();
+
+    for (local, expected) in manifest.iter() {
+        let path = PathBuf::from(local.trim_start_matches('/'));
+
+        let (size, crc) = match files.get(&path) {
+            Some(properties) => *properties,
+            None => {
+                warn!("File '{}' is listed in the manifest of '{}' but is missing.", local, archive.display());
+                continue;
+            },
+        };
+
+        if expected.size.map_or(false, |expected_size| size != expected_size) || expected.crc32.map_or(false, |expected_crc| crc != expected_crc) {
+            warn!(
+                "File '{}' in archive '{}' does not match the manifest of its mod and will be skipped! Actual size: {:#x}, actual CRC32: {:#010x}",
+                path.display(),
+                archive.display(),
+                size,
+                crc
+            );
+            mismatches.push(path);
+        }
+    }
+
+    Some(mismatches)
+}
+
+fn parse(data: &[u8], manifest_path: &Path) -> Option<HashMap<String, ManifestEntry>> {
+    match serde_json::from_slice(data) {
+        Ok(manifest) => Some(manifest),
+        Err(e) => {
+            warn!("Manifest '{}' is malformed and will not be checked. Reason: {}", manifest_path.display(), e);
+            None
+        },
+    }
+}
+
+fn compute_crc32(path: &Path) -> std::io::Result<u32> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buffer = [0u8; 0x8000];
+
+    loop {
+        let read = reader.read(&mut buffer)?;
+
+        if read == 0 {
+            break;
+        }
+
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archive_files_are_checked_against_the_manifest() {
+        let files: HashMap<PathBuf, (u64, u32)> = vec![
+            (PathBuf::from("fighter/mario/model.numdlb"), (0x10, 0x1234)),
+            (PathBuf::from("fighter/mario/model.nutexb"), (0x20, 0x5678)),
+        ]
+        .into_iter()
+        .collect();
+
+        let manifest = br#"{
+            "fighter/mario/model.numdlb": { "size": 16, "crc32": 4660 },
+            "/fighter/mario/model.nutexb": { "size": 16 },
+            "fighter/mario/missing.nutexb": { "size": 16 }
+        }"#;
+
+        let mismatches = verify_archive(Path::new("mods/Mario.zip"), manifest, &files).unwrap();
+
+        assert_eq!(mismatches, vec![PathBuf::from("fighter/mario/model.nutexb")]);
+        assert!(verify_archive(Path::new("mods/Mario.zip"), b"not a manifest", &files).is_none());
+    }
+}
@@ -0,0 +1,53 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::utils;
+
+/// File mapping paths of the data.arc to the files on the SD card that replace them, for files that can't be laid out in a mod
+pub const MANUAL_OVERRIDES_PATH: &str = "sd:/ultimate/arcropolis/manual_overrides.json";
+
+/// Reads the manual overrides, as a map of the path of a file in the data.arc to the full path of the file that replaces it.
+/// Paths on the SD card can either be absolute (`sd:/...`) or relative to the mods directory.
+pub fn load() -> HashMap<PathBuf, PathBuf> {
+    let data = match std::fs::read(MANUAL_OVERRIDES_PATH) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return HashMap::new(),
+        Err(e) => {
+            warn!("Failed to read the manual overrides at '{}'. Reason: {:?}", MANUAL_OVERRIDES_PATH, e);
+            return HashMap::new();
+        },
+    };
+
+    let overrides: HashMap<String, String> = match serde_json::from_slice(&data) {
+        Ok(overrides) => overrides,
+        Err(e) => {
+            error!("Failed to parse the manual overrides at '{}', none of them will be used. Reason: {:?}", MANUAL_OVERRIDES_PATH, e);
+            return HashMap::new();
+        },
+    };
+
+    overrides
+        .into_iter()
+        .filter_map(|(local, physical)| {
+            let physical = resolve(&physical);
+
+            if physical.is_file() {
+                Some((PathBuf::from(local.trim_start_matches('/')), physical))
+            } else {
+                warn!("Manual override for '{}' points to '{}', which is not a file. It will be skipped.", local, physical.display());
+                None
+            }
+        })
+        .collect()
+}
+
+/// Turns a path relative to the mods directory into a full path, leaving full paths as they are
+fn resolve(path: &str) -> PathBuf {
+    if path.contains(":/") {
+        PathBuf::from(path)
+    } else {
+        utils::paths::mods().as_std_path().join(Path::new(path.trim_start_matches('/')))
+    }
+}
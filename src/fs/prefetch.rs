@@ -0,0 +1,91 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use smash_arc::Hash40;
+
+use super::utils;
+use crate::{hashes, PathExtension};
+
+/// Maximum amount of threads reading files in the background at the same time
+const MAX_PREFETCH_WORKERS: usize = 2;
+
+struct PrefetchQueue {
+    hashes: VecDeque<Hash40>,
+    workers: usize,
+}
+
+// The worker count shares the lock of the queue, so that a worker can't exit right as new hashes are being queued
+static PREFETCH_QUEUE: Mutex<PrefetchQueue> = Mutex::new(PrefetchQueue {
+    hashes: VecDeque::new(),
+    workers: 0,
+});
+
+/// Reads the files in the background so that they are already in memory by the time the game requests them.
+/// Hashes that are not provided by a mod, or that are provided by the API, are skipped.
+/// Nothing is read while the file cache is disabled, since the data would be thrown away right after reading it.
+pub fn prefetch(hashes: &[Hash40]) {
+    if config::file_cache_size() == 0 {
+        debug!("Ignoring a request to prefetch {} files because the file cache is disabled.", hashes.len());
+        return;
+    }
+
+    let hashes: Vec<Hash40> = {
+        let filesystem = unsafe { crate::GLOBAL_FILESYSTEM.read().unwrap() };
+        hashes.iter().copied().filter(|hash| filesystem.is_prefetchable(*hash)).collect()
+    };
+
+    if hashes.is_empty() {
+        return;
+    }
+
+    let mut queue = PREFETCH_QUEUE.lock().unwrap();
+    queue.hashes.extend(hashes);
+
+    while queue.workers < MAX_PREFETCH_WORKERS.min(queue.hashes.len()) {
+        match std::thread::Builder::new().stack_size(0x10000).spawn(prefetch_worker) {
+            Ok(_) => queue.workers += 1,
+            Err(e) => {
+                warn!("Failed to spawn a thread to prefetch files. Reason: {:?}", e);
+                break;
+            },
+        }
+    }
+}
+
+fn prefetch_worker() {
+    loop {
+        let hash = {
+            let mut queue = PREFETCH_QUEUE.lock().unwrap();
+
+            match queue.hashes.pop_front() {
+                Some(hash) => hash,
+                None => {
+                    queue.workers -= 1;
+                    return;
+                },
+            }
+        };
+
+        // The filesystem is only locked to find the file and to cache it, since the game has to write to it while loading
+        let path = match super::get_physical_path(hash) {
+            Some(path) => path,
+            None => continue,
+        };
+
+        let data = match std::fs::read(&path) {
+            Ok(data) if path.has_extension("zst") => utils::decompress_zstd(data),
+            Ok(data) => Some(data),
+            Err(e) => {
+                warn!("Failed to prefetch {}. Reason: {:?}", path.display(), e);
+                None
+            },
+        };
+
+        if let Some(data) = data {
+            let filesystem = unsafe { crate::GLOBAL_FILESYSTEM.read().unwrap() };
+
+            if filesystem.cache_prefetched(hash, &path, &data) {
+                trace!("Prefetched file '{}' ({:#x})", hashes::find(hash), hash.0);
+            }
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::SystemTime,
+};
+
+static RNG_STATE: AtomicU64 = AtomicU64::new(0);
+
+/// A directory named like a file, such as `bgm_foo.nus3audio/`, which contains the files that can be loaded in its place.
+/// Depending on the configuration, one file is picked every time it is loaded or once for the entire session.
+pub struct RandomizerEntry {
+    candidates: Vec<PathBuf>,
+    selected: Option<usize>,
+    // Candidate picked by the last load, so that it can be reported to observers. usize::MAX until the first pick.
+    last_pick: AtomicUsize,
+}
+
+impl RandomizerEntry {
+    pub fn new(candidates: Vec<PathBuf>) -> Self {
+        let selected = if config::randomize_per_session() {
+            Some(next_index(candidates.len()))
+        } else {
+            None
+        };
+
+        Self {
+            candidates,
+            selected,
+            last_pick: AtomicUsize::new(usize::MAX),
+        }
+    }
+
+    /// Gets the full path of the file to load
+    pub fn pick(&self) -> &Path {
+        let index = self.selected.unwrap_or_else(|| next_index(self.candidates.len()));
+        self.last_pick.store(index, Ordering::Relaxed);
+        &self.candidates[index]
+    }
+
+    /// Gets the full path of the file picked by the last load, if it has been loaded at all
+    pub fn last_pick(&self) -> Option<&Path> {
+        self.candidates.get(self.last_pick.load(Ordering::Relaxed)).map(PathBuf::as_path)
+    }
+
+    /// Gets the size of the largest candidate, since the buffer has to fit whichever one gets picked
+    pub fn max_size(&self) -> usize {
+        self.candidates
+            .iter()
+            .filter_map(|path| std::fs::metadata(path).ok())
+            .map(|metadata| metadata.len() as usize)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Checks if a file is one of the candidates of a randomizer directory, which is any directory that has an extension in its name
+pub fn is_candidate(local: &Path) -> bool {
+    local
+        .parent()
+        .and_then(|parent| parent.file_name())
+        .and_then(|name| name.to_str())
+        .map_or(false, |name| !super::is_ignored_entry(name) && Path::new(name).extension().is_some())
+}
+
+// Xorshift is plenty for picking a file, and the seed only has to differ between sessions
+fn next_index(len: usize) -> usize {
+    let mut state = RNG_STATE.load(Ordering::Relaxed);
+
+    if state == 0 {
+        state = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0x2545_F491_4F6C_DD1D, |duration| duration.as_nanos() as u64)
+            | 1;
+    }
+
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    RNG_STATE.store(state, Ordering::Relaxed);
+
+    (state % len.max(1) as u64) as usize
+}
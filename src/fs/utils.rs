@@ -1,17 +1,21 @@
 use std::{
     collections::{HashMap, HashSet},
+    convert::TryFrom,
     fmt::Debug,
+    fs::File,
+    io::{Cursor, Read},
     path::{Path, PathBuf},
+    sync::LazyLock,
 };
 
 use arc_config::ToExternal;
 use orbits::{FileLoader, Tree};
-use smash_arc::Hash40;
+use smash_arc::{ArcLookup, Hash40, LoadedArc};
 
-use super::{ApiCallback, ApiLoader};
-use crate::{hashes, PathExtension};
+use super::{randomizer, ApiCallback, ApiLoader};
+use crate::{hashes, region, PathExtension};
 
-pub fn make_hash_maps<L: FileLoader>(tree: &Tree<L>) -> (HashMap<Hash40, usize>, HashMap<Hash40, PathBuf>)
+pub fn make_hash_maps<L: FileLoader>(tree: &Tree<L>, rejected: &HashSet<PathBuf>) -> (HashMap<Hash40, usize>, HashMap<Hash40, PathBuf>)
 where
     <L as FileLoader>::ErrorType: Debug,
 {
@@ -24,29 +28,97 @@ where
     // To solve this I store the hash of every file which has a regional variant which has been found, and then if a non-regional variant is found
     // it is ignored
     // - blujay
-    let mut regional_overrides = HashSet::new();
+    // Files matching at the same level (two regional variants listing the current region, or two files ending up with the same
+    // path after remapping their extension) can't be ranked, so the last one found is used and both are named in a warning.
+    let mut match_levels: HashMap<Hash40, MatchLevel> = HashMap::new();
+    let allow_empty_files = config::allow_empty_files();
     let mut size_map = HashMap::new();
     let mut path_map = HashMap::new();
+    // Only used to name both files on the SD card when they collide
+    let mut full_paths: HashMap<Hash40, PathBuf> = HashMap::new();
     tree.walk_paths(|node, ty| {
         if !ty.is_file() {
             return;
         }
 
-        if let Some(size) = tree.query_filesize(node.get_local()) {
+        // Files that failed the manifest check of their mod are left for the game to load from the data.arc
+        if rejected.contains::<Path>(node.full_path().as_ref()) {
+            return;
+        }
+
+        // Candidates of a randomizer are served through the directory that contains them
+        if randomizer::is_candidate(node.get_local()) {
+            return;
+        }
+
+        // Pre-compressed files have to report the size they decompress to, since that is what the game buffer needs to fit
+        let size = if node.get_local().has_extension("zst") {
+            if node.get_local().is_stream() {
+                error!("Compressed stream file {} is not supported and will be skipped.", node.full_path().display());
+                return;
+            }
+
+            match get_zstd_content_size(node.full_path()) {
+                Some(size) => Some(size),
+                None => {
+                    error!(
+                        "Failed to read the decompressed size of {}. The zstd frame must be created with the content size included.",
+                        node.full_path().display()
+                    );
+                    return;
+                },
+            }
+        } else {
+            tree.query_filesize(node.get_local())
+        };
+
+        // An empty replacement is almost always the result of an interrupted copy, which the game would choke on
+        if size == Some(0) && !allow_empty_files {
+            warn!("File {} is empty, likely an incomplete copy. It will be skipped.", node.full_path().display());
+            return;
+        }
+
+        if let Some(size) = size {
             match node.get_local().smash_hash() {
                 Ok(hash) => {
-                    if regional_overrides.contains(&hash) {
-                        return;
-                    }
+                    let is_regional_variant = node.get_local().to_str().map_or(false, region::has_marker);
+                    let level = if is_regional_variant { MatchLevel::Regional } else { MatchLevel::Agnostic };
 
-                    let is_regional_variant = if let Some(node) = node.get_local().to_str() { node.contains('+') } else { false };
+                    if let Some(existing_level) = match_levels.get(&hash).copied() {
+                        if level < existing_level {
+                            return;
+                        }
 
-                    size_map.insert(hash, size);
-                    path_map.insert(hash, node.get_local().to_path_buf());
+                        if let Some(existing) = path_map.get(&hash).filter(|existing| *existing != node.get_local()) {
+                            if level == existing_level {
+                                // Remapping the extension can make two different files end up with the same path in the data.arc,
+                                // which is never something the mod author intended
+                                let reason = if is_remapped_extension(existing) || is_remapped_extension(node.get_local()) {
+                                    "after remapping its extension"
+                                } else if level == MatchLevel::Regional {
+                                    "for the current region"
+                                } else {
+                                    // Such as a file and its pre-compressed variant, which are hashed as the same path
+                                    "because both replace the same file"
+                                };
+
+                                let existing = full_paths.get(&hash).map_or(existing.as_path(), PathBuf::as_path);
 
-                    if is_regional_variant {
-                        regional_overrides.insert(hash);
+                                warn!(
+                                    "File '{}' collides with file '{}' {}. Only '{}' will be used.",
+                                    node.full_path().display(),
+                                    existing.display(),
+                                    reason,
+                                    node.full_path().display()
+                                );
+                            }
+                        }
                     }
+
+                    size_map.insert(hash, size);
+                    path_map.insert(hash, node.get_local().to_path_buf());
+                    full_paths.insert(hash, node.full_path().to_path_buf());
+                    match_levels.insert(hash, level);
                 },
                 Err(e) => error!("Failed to get hash for {}. Reason: {:?}", node.get_local().display(), e),
             }
@@ -58,6 +130,155 @@ where
     (size_map, path_map)
 }
 
+/// How specifically a file targets the current region, files with a higher level win over the ones with a lower level
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum MatchLevel {
+    Agnostic,
+    Regional,
+}
+
+/// Gathers the candidates of every randomizer directory in the tree, keyed by the path of the file they are loaded in place of
+pub fn collect_randomizers<L: FileLoader>(tree: &Tree<L>) -> HashMap<PathBuf, Vec<PathBuf>>
+where
+    <L as FileLoader>::ErrorType: Debug,
+{
+    let mut randomizers: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+
+    tree.walk_paths(|node, ty| {
+        if !ty.is_file() || !randomizer::is_candidate(node.get_local()) {
+            return;
+        }
+
+        if let Some(parent) = node.get_local().parent() {
+            randomizers.entry(parent.to_path_buf()).or_default().push(node.full_path().to_path_buf());
+        }
+    });
+
+    randomizers
+}
+
+/// Logs how many files of each extension were discovered, and warns about the extensions that no file of the data.arc uses
+pub fn report_extensions(arc: &LoadedArc, paths: &HashMap<Hash40, PathBuf>) {
+    let mut tally: HashMap<String, (usize, &Path)> = HashMap::new();
+
+    for path in paths.values() {
+        // Streams are not part of the file tables, and files named after their hash don't have an extension to check
+        if path.is_stream() {
+            continue;
+        }
+
+        if let Some(extension) = game_extension(path) {
+            tally.entry(extension).or_insert((0, path.as_path())).0 += 1;
+        }
+    }
+
+    if tally.is_empty() {
+        return;
+    }
+
+    let mut extensions: Vec<_> = tally.into_iter().collect();
+    extensions.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    info!(
+        "Discovered files by extension: {}",
+        extensions.iter().map(|(extension, (count, _))| format!("{}: {}", extension, count)).collect::<Vec<_>>().join(", ")
+    );
+
+    let known: HashSet<Hash40> = arc.get_file_paths().iter().map(|path| path.ext.hash40()).collect();
+
+    for (extension, (count, example)) in extensions {
+        if !known.contains(&Hash40::from(extension.as_str())) {
+            warn!(
+                "{} file(s) use the extension '{}', which no file of the game uses. They are possibly misnamed, such as '{}'.",
+                count,
+                extension,
+                example.display()
+            );
+        }
+    }
+}
+
+/// Gets the extension a file has once it is hashed, accounting for remapped extensions and pre-compressed files
+fn game_extension(path: &Path) -> Option<String> {
+    let path = if path.has_extension("zst") { Path::new(path.file_stem()?) } else { path };
+
+    let extension = path.extension()?.to_str()?.to_lowercase();
+
+    match extension.as_str() {
+        "mp4" => Some(String::from("webm")),
+        "lua" => Some(String::from("lc")),
+        _ => Some(extension),
+    }
+}
+
+/// Checks if the extension of a file is replaced by another one when hashing it, such as .mp4 files being loaded as .webm
+fn is_remapped_extension(path: &Path) -> bool {
+    path.has_extension("mp4") || path.has_extension("lua")
+}
+
+/// Reads the frame header of a zstd-compressed file and returns the decompressed size stored in it, if there is one
+pub fn get_zstd_content_size<P: AsRef<Path>>(path: P) -> Option<usize> {
+    const ZSTD_MAGIC: u32 = 0xFD2F_B528;
+
+    // magic (4) + frame header descriptor (1) + window descriptor (1) + dictionary id (4) + content size (8)
+    let mut header = [0u8; 18];
+    let mut file = File::open(path).ok()?;
+    let read = file.read(&mut header).ok()?;
+    let header = &header[..read];
+
+    if header.len() < 5 || u32::from_le_bytes([header[0], header[1], header[2], header[3]]) != ZSTD_MAGIC {
+        return None;
+    }
+
+    let descriptor = header[4];
+    let single_segment = descriptor & 0x20 != 0;
+    let dict_id_size = [0, 1, 2, 4][(descriptor & 0x3) as usize];
+    let content_size_size = match descriptor >> 6 {
+        0 if single_segment => 1,
+        0 => 0,
+        1 => 2,
+        2 => 4,
+        _ => 8,
+    };
+
+    // The content size is optional, and without it we have no way of knowing how large of a buffer the game needs
+    if content_size_size == 0 {
+        return None;
+    }
+
+    let start = 5 + if single_segment { 0 } else { 1 } + dict_id_size;
+    let field = header.get(start..start + content_size_size)?;
+
+    let mut bytes = [0u8; 8];
+    bytes[..content_size_size].copy_from_slice(field);
+    let size = u64::from_le_bytes(bytes);
+
+    // The two byte variant is stored with an offset of 256
+    let size = if content_size_size == 2 { size + 256 } else { size };
+
+    usize::try_from(size).ok()
+}
+
+/// Decompresses the data of a zstd-compressed replacement file
+pub fn decompress_zstd(data: Vec<u8>) -> Option<Vec<u8>> {
+    let mut decoder = match ruzstd::StreamingDecoder::new(Cursor::new(data)) {
+        Ok(decoder) => decoder,
+        Err(e) => {
+            error!("Failed to read zstd frame. Reason: {:?}", e);
+            return None;
+        },
+    };
+
+    let mut out = Vec::new();
+    match decoder.read_to_end(&mut out) {
+        Ok(_) => Some(out),
+        Err(e) => {
+            error!("Failed to decompress zstd data. Reason: {:?}", e);
+            None
+        },
+    }
+}
+
 pub fn get_required_nus3banks<L: FileLoader>(tree: &Tree<L>, unshare_blacklist: &[hash40::Hash40]) -> HashSet<PathBuf>
 where
     <L as FileLoader>::ErrorType: Debug,
@@ -94,6 +315,14 @@ where
     nus3audio_deps
 }
 
+/// Prefix of every root of the API tree, which does not exist on the SD card and only serves to identify virtual files
+pub static API_PREFIX: LazyLock<PathBuf> = LazyLock::new(|| PathBuf::from(config::virtual_root()));
+
+/// Gets the root of the API tree used for a kind of virtual file, such as `api:/patch-prc`
+pub fn api_root(kind: &str) -> PathBuf {
+    API_PREFIX.join(kind)
+}
+
 pub fn add_file_to_api_tree<P: AsRef<Path>, Q: AsRef<Path>>(
     tree: &mut Tree<ApiLoader>,
     root: P,
@@ -129,10 +358,8 @@ pub fn add_prc_patch<P: AsRef<Path>, Q: AsRef<Path>>(tree: &mut Tree<ApiLoader>,
         unreachable!()
     };
     let base_local = if let Some(name) = base_local.file_name().and_then(|os_str| os_str.to_str()) {
-        if let Some(idx) = name.find('+') {
-            let mut new_name = name.to_string();
-            new_name.replace_range(idx..idx + 6, "");
-            base_local.with_file_name(new_name)
+        if name.contains('+') {
+            base_local.with_file_name(region::strip_marker(name))
         } else {
             base_local
         }
@@ -142,8 +369,8 @@ pub fn add_prc_patch<P: AsRef<Path>, Q: AsRef<Path>>(tree: &mut Tree<ApiLoader>,
     let full_path = phys_root.as_ref().join(local); // need the full path so that our API loader can load it
     match base_local.smash_hash() {
         Ok(hash) => {
-            tree.insert_file("api:/patch-prc", &base_local);
-            tree.loader.push_entry(hash, Path::new("api:/patch-prc"), ApiCallback::None);
+            tree.insert_file(&api_root("patch-prc"), &base_local);
+            tree.loader.push_entry(hash, &api_root("patch-prc"), ApiCallback::None);
             // We need to add our file to the vector of patch files
             tree.loader.insert_prc_patch(hash, &full_path);
             if let Some(local) = local.to_str() {
@@ -168,11 +395,9 @@ pub fn add_msbt_patch<P: AsRef<Path>, Q: AsRef<Path>>(tree: &mut Tree<ApiLoader>
     let mut is_current_region = true;
 
     let base_local = if let Some(name) = base_local.file_name().and_then(|os_str| os_str.to_str()) {
-        if let Some(idx) = name.find('+') {
-            is_current_region = (&name[idx + 1..idx + 6] == format!("{}", config::region())); //Check if XMSBT's region is current region
-            let mut new_name = name.to_string();
-            new_name.replace_range(idx..idx + 6, "");
-            base_local.with_file_name(new_name)
+        if name.contains('+') {
+            is_current_region = !region::is_out_of_region(name, config::asset_region()); //Check if XMSBT's region is current region
+            base_local.with_file_name(region::strip_marker(name))
         } else {
             base_local
         }
@@ -182,8 +407,8 @@ pub fn add_msbt_patch<P: AsRef<Path>, Q: AsRef<Path>>(tree: &mut Tree<ApiLoader>
     let full_path = phys_root.as_ref().join(local); // need the full path so that our API loader can load it
     match base_local.smash_hash() {
         Ok(hash) => {
-            tree.insert_file("api:/patch-msbt", &base_local);
-            tree.loader.push_entry(hash, Path::new("api:/patch-msbt"), ApiCallback::None);
+            tree.insert_file(&api_root("patch-msbt"), &base_local);
+            tree.loader.push_entry(hash, &api_root("patch-msbt"), ApiCallback::None);
             // We need to add our file to the vector of patch files
             tree.loader.insert_msbt_patch(hash, &full_path);
             if let Some(local) = local.to_str() {
@@ -208,10 +433,8 @@ pub fn add_nus3audio_patch<P: AsRef<Path>, Q: AsRef<Path>>(tree: &mut Tree<ApiLo
     let base_local = local.with_extension("nus3audio");
 
     let base_local = if let Some(name) = base_local.file_name().and_then(|os_str| os_str.to_str()) {
-        if let Some(idx) = name.find('+') {
-            let mut new_name = name.to_string();
-            new_name.replace_range(idx..idx + 6, "");
-            base_local.with_file_name(new_name)
+        if name.contains('+') {
+            base_local.with_file_name(region::strip_marker(name))
         } else {
             base_local
         }
@@ -221,8 +444,8 @@ pub fn add_nus3audio_patch<P: AsRef<Path>, Q: AsRef<Path>>(tree: &mut Tree<ApiLo
     let full_path = phys_root.as_ref().join(local); // need the full path so that our API loader can load it
     match base_local.smash_hash() {
         Ok(hash) => {
-            tree.insert_file("api:/patch-nus3audio", &base_local);
-            tree.loader.push_entry(hash, Path::new("api:/patch-nus3audio"), ApiCallback::None);
+            tree.insert_file(&api_root("patch-nus3audio"), &base_local);
+            tree.loader.push_entry(hash, &api_root("patch-nus3audio"), ApiCallback::None);
             // We need to add our file to the vector of patch files
             tree.loader.insert_nus3audio_patch(hash, &full_path);
             if let Some(local) = local.to_str() {
@@ -245,10 +468,8 @@ pub fn add_motionlist_patch<P: AsRef<Path>, Q: AsRef<Path>>(tree: &mut Tree<ApiL
     let base_local = local.with_extension("bin");
 
     let base_local = if let Some(name) = base_local.file_name().and_then(|os_str| os_str.to_str()) {
-        if let Some(idx) = name.find('+') {
-            let mut new_name = name.to_string();
-            new_name.replace_range(idx..idx + 6, "");
-            base_local.with_file_name(new_name)
+        if name.contains('+') {
+            base_local.with_file_name(region::strip_marker(name))
         } else {
             base_local
         }
@@ -260,8 +481,8 @@ pub fn add_motionlist_patch<P: AsRef<Path>, Q: AsRef<Path>>(tree: &mut Tree<ApiL
         if name.to_str().unwrap().contains(&"motion_list") {
             match base_local.smash_hash() {
                 Ok(hash) => {
-                    tree.insert_file("api:/patch-motionlist", &base_local);
-                    tree.loader.push_entry(hash, Path::new("api:/patch-motionlist"), ApiCallback::None);
+                    tree.insert_file(&api_root("patch-motionlist"), &base_local);
+                    tree.loader.push_entry(hash, &api_root("patch-motionlist"), ApiCallback::None);
                     // We need to add our file to the vector of patch files
                     tree.loader.insert_motionlist_patch(hash, &full_path);
                     if let Some(local) = local.to_str() {
@@ -286,15 +507,48 @@ pub fn add_motionlist_patch<P: AsRef<Path>, Q: AsRef<Path>>(tree: &mut Tree<ApiL
     None
 }
 
+/// Adds a JSON patch file and information to the API loader
+pub fn add_json_patch<P: AsRef<Path>, Q: AsRef<Path>>(tree: &mut Tree<ApiLoader>, phys_root: P, local: Q) -> Option<Hash40> {
+    let local = local.as_ref();
+    let base_local = local.with_extension("json");
+    let base_local = if let Some(name) = base_local.file_name().and_then(|os_str| os_str.to_str()) {
+        if name.contains('+') {
+            base_local.with_file_name(region::strip_marker(name))
+        } else {
+            base_local
+        }
+    } else {
+        base_local
+    };
+    let full_path = phys_root.as_ref().join(local); // need the full path so that our API loader can load it
+    match base_local.smash_hash() {
+        Ok(hash) => {
+            tree.insert_file(&api_root("patch-json"), &base_local);
+            tree.loader.push_entry(hash, &api_root("patch-json"), ApiCallback::None);
+            // We need to add our file to the vector of patch files
+            tree.loader.insert_json_patch(hash, &full_path);
+            if let Some(local) = local.to_str() {
+                hashes::add(local);
+            }
+            if let Some(base_local) = base_local.to_str() {
+                hashes::add(base_local);
+            }
+            Some(hash)
+        },
+        Err(e) => {
+            error!("Could not add file {} to API tree. Reason: {:?}", full_path.display(), e);
+            None
+        },
+    }
+}
+
 pub fn add_bgm_property_patch<P: AsRef<Path>, Q: AsRef<Path>>(tree: &mut Tree<ApiLoader>, phys_root: P, local: Q) -> Option<Hash40> {
     let local = local.as_ref();
     let base_local = local.with_extension("bin");
 
     let base_local = if let Some(name) = base_local.file_name().and_then(|os_str| os_str.to_str()) {
-        if let Some(idx) = name.find('+') {
-            let mut new_name = name.to_string();
-            new_name.replace_range(idx..idx + 6, "");
-            base_local.with_file_name(new_name)
+        if name.contains('+') {
+            base_local.with_file_name(region::strip_marker(name))
         } else {
             base_local
         }
@@ -306,8 +560,8 @@ pub fn add_bgm_property_patch<P: AsRef<Path>, Q: AsRef<Path>>(tree: &mut Tree<Ap
         if name.to_str().unwrap().contains(&"bgm_property") {
             match base_local.smash_hash() {
                 Ok(hash) => {
-                    tree.insert_file("api:/patch-bgm_property", &base_local);
-                    tree.loader.push_entry(hash, Path::new("api:/patch-bgm_property"), ApiCallback::None);
+                    tree.insert_file(&api_root("patch-bgm_property"), &base_local);
+                    tree.loader.push_entry(hash, &api_root("patch-bgm_property"), ApiCallback::None);
                     // We need to add our file to the vector of patch files
                     tree.loader.insert_bgm_property_patch(hash, &full_path);
                     if let Some(local) = local.to_str() {
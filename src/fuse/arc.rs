@@ -1,9 +1,9 @@
-use std::{io::Write, str::FromStr, sync::LazyLock};
+use std::{io::Write, sync::LazyLock};
 
 use nn_fuse::{AccessorResult, DAccessor, DirectoryAccessor, FAccessor, FileAccessor, FileSystemAccessor, FsAccessor, FsEntryType};
 use smash_arc::{ArcFile, ArcLookup, Hash40, Region};
 
-use crate::PathExtension;
+use crate::{region, PathExtension};
 
 pub static ARC_FILE: LazyLock<ArcFile> = LazyLock::new(|| ArcFile::open("rom:/data.arc").unwrap());
 
@@ -51,16 +51,14 @@ impl FileSystemAccessor for ArcFuse {
         let write = mode >> 1 & 1;
         let append = mode >> 2 & 1;
         debug!("Path: {}, read: {}, write: {}, append: {}", path.display(), read, write, append);
-        let mut file_region = config::region();
-        let mut new_path = path.display().to_string();
-        for region in crate::REGIONS.iter() {
-            if new_path.contains(region) {
-                let region_string = format!("+{}", region);
-                new_path.remove_matches(&region_string);
-                file_region = Region::from_str(region).unwrap();
-                let _path = std::path::Path::new(&new_path);
-            }
-        }
+        // Use the region from the marker in the file name if there is one, preferring the region the game is running in if it is listed.
+        // Otherwise fall back to the region the game is running in.
+        let regions = path.file_name().and_then(|name| name.to_str()).map(region::from_file_name).unwrap_or_default();
+        let mut file_region = if regions.contains(&config::region()) {
+            config::region()
+        } else {
+            regions.first().copied().unwrap_or_else(config::region)
+        };
 
         let hash = path.smash_hash().unwrap();
         match ARC_FILE.get_file_info_from_hash(hash) {
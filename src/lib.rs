@@ -29,6 +29,7 @@ mod fuse;
 mod hashes;
 mod logging;
 mod offsets;
+mod region;
 mod replacement;
 mod resource;
 #[cfg(feature = "online")]
@@ -129,10 +130,19 @@ impl PathExtension for Path {
             .replace(".mp4", ".webm")
             .replace(".lua", ".lc");
 
-        if let Some(regional_idx) = path.find('+') {
-            path.replace_range(regional_idx..regional_idx + 6, "")
+        // Pre-compressed replacements are hashed as the file they decompress into
+        if path.ends_with(".zst") {
+            path.truncate(path.len() - ".zst".len());
         }
 
+        // Mods authored on Windows can use backslashes, but the game only knows about single forward slashes
+        let mut path = path.replace('\\', "/");
+        while path.contains("//") {
+            path = path.replace("//", "/");
+        }
+
+        let path = region::strip_marker(&path);
+
         Ok(Hash40::from(path.trim_start_matches('/')))
     }
 }
@@ -150,10 +160,6 @@ fn get_path_from_hash(hash: Hash40) -> PathBuf {
     }
 }
 
-pub const REGIONS: &[&str] = &[
-    "jp_ja", "us_en", "us_fr", "us_es", "eu_en", "eu_fr", "eu_es", "eu_de", "eu_nl", "eu_it", "eu_ru", "kr_ko", "zh_cn", "zh_tw",
-];
-
 /// Initializes the `nn::time` library, for creating a log file based off of the current time. For some reason Smash does not initialize this
 fn init_time() {
     unsafe {
@@ -262,9 +268,14 @@ fn initial_loading(_ctx: &InlineCtx) {
     
     *filesystem = filesystem.take().finish(arc).unwrap();
 
-    filesystem.process_mods();
-    filesystem.share_hashes();
-    filesystem.patch_files();
+    // Only report what would be loaded, without touching the tables of the game
+    if config::dry_run() {
+        filesystem.dry_run();
+    } else {
+        filesystem.process_mods();
+        filesystem.share_hashes();
+        filesystem.patch_files();
+    }
 
     if config::debug_enabled() {
         let mut output = BufWriter::new(std::fs::File::create("sd:/ultimate/arcropolis/filesystem_dump.txt").unwrap());
@@ -521,3 +532,30 @@ pub fn main() {
 
     api::event::setup();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(path: &str) -> Hash40 {
+        Path::new(path).smash_hash().unwrap()
+    }
+
+    #[test]
+    fn separators_are_normalized_before_hashing() {
+        let expected = hash("fighter/mario/model.numdlb");
+
+        assert_eq!(hash("fighter\\mario//model.numdlb"), expected);
+        assert_eq!(hash("fighter\\\\mario\\model.numdlb"), expected);
+        assert_eq!(hash("/fighter//mario/model.numdlb"), expected);
+    }
+
+    #[test]
+    fn compressed_and_regional_paths_hash_like_the_original_file() {
+        let expected = hash("ui/message/msg_menu.msbt");
+
+        assert_eq!(hash("ui\\message//msg_menu.msbt.zst"), expected);
+        assert_eq!(hash("ui\\message\\msg_menu+us_en.msbt"), expected);
+        assert_eq!(hash("ui//message\\msg_menu+us_en,eu_en.msbt.zst"), expected);
+    }
+}
@@ -0,0 +1,97 @@
+use std::{ops::Range, str::FromStr};
+
+use smash_arc::Region;
+
+/// Parses a locale code such as `us_en` into the region it represents
+pub fn from_marker(marker: &str) -> Option<Region> {
+    match Region::from_str(marker) {
+        Ok(Region::None) | Err(_) => None,
+        Ok(region) => Some(region),
+    }
+}
+
+/// Finds the range of the region marker in a name, from the `+` up until the extension or the next path separator
+fn marker_range(name: &str) -> Option<Range<usize>> {
+    let start = name.find('+')?;
+    let end = name[start + 1..].find(|c| c == '.' || c == '/').map_or(name.len(), |len| start + 1 + len);
+    Some(start..end)
+}
+
+/// Gets every region listed in the marker of a file name, such as `msg_menu+us_en.msbt` or `msg_menu+us_en,eu_en.msbt`
+pub fn from_file_name(name: &str) -> Vec<Region> {
+    match marker_range(name) {
+        Some(range) => name[range.start + 1..range.end].split(',').filter_map(from_marker).collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Checks if a name has a region marker listing at least one region.
+/// A `+` that is not followed by a valid locale code, such as in `foo+.bntx` or `foo+us.bntx`, is simply part of the name.
+pub fn has_marker(name: &str) -> bool {
+    !from_file_name(name).is_empty()
+}
+
+/// Checks if a file name has a region marker that does not list the provided region.
+/// Files without a valid marker are valid for every region.
+pub fn is_out_of_region(name: &str, region: Region) -> bool {
+    let regions = from_file_name(name);
+    !regions.is_empty() && !regions.contains(&region)
+}
+
+/// Removes the region marker from a name, so that it matches the path of the file in the data.arc
+pub fn strip_marker(name: &str) -> String {
+    let mut name = name.to_string();
+
+    if has_marker(&name) {
+        if let Some(range) = marker_range(&name) {
+            name.replace_range(range, "");
+        }
+    }
+
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markers_list_every_region() {
+        assert!(from_file_name("msg_menu+us_en.msbt") == vec![Region::UsEnglish]);
+        assert!(from_file_name("msg_menu+us_en,eu_en.msbt") == vec![Region::UsEnglish, Region::EuEnglish]);
+        assert!(from_file_name("ui/message+jp_ja/msg_menu.msbt") == vec![Region::Japanese]);
+        assert!(from_file_name("msg_menu.msbt").is_empty());
+    }
+
+    #[test]
+    fn truncated_and_malformed_markers_are_part_of_the_name() {
+        for name in ["foo+.bntx", "foo+us.bntx", "foo+", "foo+xx_yy.bntx", "foo+,.bntx"] {
+            assert!(from_file_name(name).is_empty(), "{}", name);
+            assert!(!has_marker(name), "{}", name);
+            assert!(!is_out_of_region(name, Region::UsEnglish), "{}", name);
+            assert_eq!(strip_marker(name), name);
+        }
+    }
+
+    #[test]
+    fn invalid_codes_in_a_list_are_skipped() {
+        assert!(from_file_name("foo+us,eu_en.bntx") == vec![Region::EuEnglish]);
+        assert!(has_marker("foo+us,eu_en.bntx"));
+        assert_eq!(strip_marker("foo+us,eu_en.bntx"), "foo.bntx");
+    }
+
+    #[test]
+    fn files_are_only_out_of_region_when_the_marker_excludes_it() {
+        assert!(is_out_of_region("msg_menu+us_en.msbt", Region::Japanese));
+        assert!(!is_out_of_region("msg_menu+us_en.msbt", Region::UsEnglish));
+        assert!(!is_out_of_region("msg_menu+us_en,jp_ja.msbt", Region::Japanese));
+        assert!(!is_out_of_region("msg_menu.msbt", Region::Japanese));
+    }
+
+    #[test]
+    fn stripping_keeps_the_extension_and_the_rest_of_the_path() {
+        assert_eq!(strip_marker("ui/message/msg_menu+us_en.msbt"), "ui/message/msg_menu.msbt");
+        assert_eq!(strip_marker("ui/message+us_en/msg_menu.msbt"), "ui/message/msg_menu.msbt");
+        assert_eq!(strip_marker("ui/message/msg_menu+us_en"), "ui/message/msg_menu");
+    }
+}
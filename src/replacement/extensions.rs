@@ -186,6 +186,7 @@ pub trait LoadedArcEx {
     fn get_file_hash_to_path_index_mut(&mut self) -> &mut [HashToIndex];
     fn get_bucket_for_hash_mut(&mut self, hash: Hash40) -> &mut [HashToIndex];
     fn patch_filedata(&mut self, hash: Hash40, size: u32, region: Region) -> Result<u32, LookupError>;
+    fn resize_filedata(&mut self, hash: Hash40, size: u32, region: Region) -> Result<u32, LookupError>;
     fn change_hash_lookup(&mut self, hash: Hash40, index: FilePathIdx) -> Result<(), LookupError>;
     fn get_shared_file(&self, hash: Hash40) -> Result<FilePathIdx, LookupError>;
     fn resort_file_hashes(&mut self);
@@ -234,6 +235,22 @@ impl LoadedArcEx for LoadedArc {
         Ok(old_size)
     }
 
+    // Same as patch_filedata, but files stored uncompressed also get their compressed size changed so that the game
+    // does not read more data than the new size into the buffer. Required when making a file smaller.
+    fn resize_filedata(&mut self, hash: Hash40, size: u32, region: Region) -> Result<u32, LookupError> {
+        let old_size = self.patch_filedata(hash, size, region)?;
+
+        let file_info = *self.get_file_info_from_hash(hash)?;
+        let region = if file_info.flags.is_regional() { region } else { Region::None };
+        let file_data = self.get_file_data_mut(&file_info, region);
+
+        if !file_data.flags.compressed() {
+            file_data.comp_size = size;
+        }
+
+        Ok(old_size)
+    }
+
     fn change_hash_lookup(&mut self, hash: Hash40, index: FilePathIdx) -> Result<(), LookupError> {
         let bucket = self.get_bucket_for_hash_mut(hash);
 
@@ -32,7 +32,8 @@ fn inflate_incoming(ctx: &InlineCtx) {
 
     let mut fs = unsafe { GLOBAL_FILESYSTEM.write().unwrap() };
 
-    let should_add = if let Some(path) = fs.hash(path_hash) {
+    // Only the local path is needed here, resolving the actual path would make randomizers pick a file that is never loaded
+    let should_add = if let Some(path) = fs.local_hash(path_hash) {
         info!("Added file '{}' to the queue.", path.display().yellow());
         true
     } else {
@@ -137,6 +138,11 @@ pub fn handle_file_replace(hash: Hash40) {
             size,
             resource::res_service().buffer_size
         );
+
+        // Release the filesystem before handing control to the observers
+        drop(fs);
+
+        crate::api::observer::notify_load(hash, crate::fs::get_loaded_path(hash).as_deref());
     } else {
         warn!(
             "Failed to load file '{}' ({:#x}) into buffer with size {:#X}",